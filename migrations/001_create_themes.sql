@@ -0,0 +1,6 @@
+-- Theme overrides per scope, read by `theme::load` and written by `theme::set`.
+-- `scope` is `Scope::key()`: global, network:<n>, channel:<n>/<c> or host:<h>.
+CREATE TABLE IF NOT EXISTS themes (
+    scope VARCHAR(255) NOT NULL PRIMARY KEY,
+    theme TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Plain mode per host, read by `author::cache::get` and written by
+-- `author::cache::set`.
+ALTER TABLE colors ADD COLUMN plain BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,6 @@
+-- Number locale per scope, read by `locale::load` and written by `locale::set`.
+-- `scope` is `Scope::key()`, as in `themes`; `locale` is a name like `de-CH`.
+CREATE TABLE IF NOT EXISTS locales (
+    scope VARCHAR(255) NOT NULL PRIMARY KEY,
+    locale VARCHAR(16) NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Background color per host, 0-98, or NULL for none. Kept out of `color1`
+-- and `color2` so those stay two-digit codes.
+ALTER TABLE colors ADD COLUMN bg TINYINT UNSIGNED NULL DEFAULT NULL;
@@ -0,0 +1,456 @@
+use crate::ffi::reinze_free_color_result;
+use crate::{locale, theme};
+use crate::ColorResult;
+use anyhow::{bail, Result};
+use std::mem::{offset_of, size_of};
+use std::os::raw::c_char;
+
+/// Version of the `PluginContext` layout produced and expected by this build.
+/// Bump this whenever a field is appended to `PluginContext`.
+pub const PLUGIN_ABI_VERSION: u32 = 7;
+
+/// The original four-field layout, which predates `abi_version` and `size`.
+/// Plugins built against it do not export `reinze_plugin_abi`.
+pub const PLUGIN_ABI_VERSION_LEGACY: u32 = 1;
+
+/// Context handed from the host to a plugin on every call.
+///
+/// New fields are only ever appended, so any version is a valid prefix of
+/// the next one. `abi_version` and `size` describe which fields the host
+/// actually filled in; a host newer than the plugin may send more fields than
+/// the plugin knows about, and those are ignored.
+#[repr(C)]
+pub struct PluginContext {
+    pub cmd: *const c_char,
+    pub param: *const c_char,
+    pub author: *const c_char,
+    pub color: unsafe extern "C" fn(*const c_char, *const c_char) -> ColorResult,
+    pub abi_version: u32,
+    pub size: usize,
+    /// Added in version 3. Releases a `ColorResult` returned by `color`
+    /// using the allocator that created it.
+    pub free_color: Option<unsafe extern "C" fn(ColorResult)>,
+    /// Added in version 4. Network and channel the command came from; either
+    /// may be null, e.g. for a private message.
+    pub network: *const c_char,
+    pub channel: *const c_char,
+    /// Added in version 5. Whether the author asked for output without
+    /// control codes. `color` still returns their real codes either way.
+    pub plain: bool,
+    /// Added in version 6. The theme the host resolved for this author,
+    /// channel and network, in the stored `label=14,value=04` form; null for
+    /// the plugin's own defaults.
+    pub theme: *const c_char,
+    /// Added in version 6. Stores a theme in the host so every plugin sees
+    /// it: a `Scope::key()` and the stored form, empty to clear it. Returns
+    /// false if it wasn't saved.
+    pub set_theme: Option<unsafe extern "C" fn(*const c_char, *const c_char) -> bool>,
+    /// Added in version 7. The locale the host resolved, by name such as
+    /// `de-CH`; null for `en`.
+    pub locale: *const c_char,
+    /// Added in version 7. Stores a locale in the host, like `set_theme`: a
+    /// `Scope::key()` and a locale name, empty to clear it.
+    pub set_locale: Option<unsafe extern "C" fn(*const c_char, *const c_char) -> bool>,
+}
+
+/// The unversioned layout of `PluginContext` used by ABI version 1.
+#[repr(C)]
+pub struct LegacyPluginContext {
+    pub cmd: *const c_char,
+    pub param: *const c_char,
+    pub author: *const c_char,
+    pub color: unsafe extern "C" fn(*const c_char, *const c_char) -> ColorResult,
+}
+
+impl PluginContext {
+    pub fn new(
+        cmd: *const c_char,
+        param: *const c_char,
+        author: *const c_char,
+        color: unsafe extern "C" fn(*const c_char, *const c_char) -> ColorResult,
+    ) -> Self {
+        Self {
+            cmd,
+            param,
+            author,
+            color,
+            abi_version: PLUGIN_ABI_VERSION,
+            size: size_of::<Self>(),
+            free_color: Some(reinze_free_color_result),
+            network: std::ptr::null(),
+            channel: std::ptr::null(),
+            plain: false,
+            theme: std::ptr::null(),
+            set_theme: Some(theme::theme_ffi),
+            locale: std::ptr::null(),
+            set_locale: Some(locale::locale_ffi),
+        }
+    }
+
+    /// Like `new`, but with the header of an older layout, for a plugin that
+    /// `negotiate` says was built against `version`. Versions newer than
+    /// this build are capped to it. Plugins on version 1 expect `as_legacy`
+    /// instead, so it is treated as version 2.
+    pub fn for_version(
+        cmd: *const c_char,
+        param: *const c_char,
+        author: *const c_char,
+        color: unsafe extern "C" fn(*const c_char, *const c_char) -> ColorResult,
+        version: u32,
+    ) -> Self {
+        let version = version.clamp(2, PLUGIN_ABI_VERSION);
+        Self {
+            abi_version: version,
+            size: Self::size_for(version),
+            ..Self::new(cmd, param, author, color)
+        }
+    }
+
+    /// Set where the command came from.
+    pub fn with_origin(mut self, network: *const c_char, channel: *const c_char) -> Self {
+        self.network = network;
+        self.channel = channel;
+        self
+    }
+
+    /// Set whether the author wants plain output.
+    pub fn with_plain(mut self, plain: bool) -> Self {
+        self.plain = plain;
+        self
+    }
+
+    /// Set the resolved theme, e.g. `theme::resolve(..).to_overrides()` as
+    /// a C string.
+    pub fn with_theme(mut self, theme: *const c_char) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Set the resolved locale's name, e.g. `locale::resolve(..).name` as a
+    /// C string.
+    pub fn with_locale(mut self, locale: *const c_char) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// The host's free function for `ColorResult`, if its layout has one.
+    pub fn free_color(&self) -> Option<unsafe extern "C" fn(ColorResult)> {
+        if self.has_version(3) {
+            self.free_color
+        } else {
+            None
+        }
+    }
+
+    /// The network and channel, or nulls if the host's layout predates them.
+    pub fn origin(&self) -> (*const c_char, *const c_char) {
+        if self.has_version(4) {
+            (self.network, self.channel)
+        } else {
+            (std::ptr::null(), std::ptr::null())
+        }
+    }
+
+    /// Whether the author wants plain output; `false` if the host's layout
+    /// predates the flag.
+    pub fn plain(&self) -> bool {
+        self.has_version(5) && self.plain
+    }
+
+    /// The resolved theme, or null if the host's layout predates it.
+    pub fn theme(&self) -> *const c_char {
+        if self.has_version(6) {
+            self.theme
+        } else {
+            std::ptr::null()
+        }
+    }
+
+    /// The host's function for storing themes, if its layout has one.
+    pub fn set_theme(&self) -> Option<unsafe extern "C" fn(*const c_char, *const c_char) -> bool> {
+        if self.has_version(6) {
+            self.set_theme
+        } else {
+            None
+        }
+    }
+
+    /// The resolved locale's name, or null if the host's layout predates it.
+    pub fn locale(&self) -> *const c_char {
+        if self.has_version(7) {
+            self.locale
+        } else {
+            std::ptr::null()
+        }
+    }
+
+    /// The host's function for storing locales, if its layout has one.
+    pub fn set_locale(&self) -> Option<unsafe extern "C" fn(*const c_char, *const c_char) -> bool> {
+        if self.has_version(7) {
+            self.set_locale
+        } else {
+            None
+        }
+    }
+
+    fn has_version(&self, version: u32) -> bool {
+        self.abi_version >= version && self.size >= Self::size_for(version)
+    }
+
+    /// Upgrade a context built with the version 1 layout.
+    pub fn from_legacy(ctx: &LegacyPluginContext) -> Self {
+        Self::new(ctx.cmd, ctx.param, ctx.author, ctx.color)
+    }
+
+    /// View this context through the version 1 layout, which is what a
+    /// plugin built before versioning existed will read.
+    pub fn as_legacy(&self) -> &LegacyPluginContext {
+        // Safe because `LegacyPluginContext` is a `#[repr(C)]` prefix of `Self`.
+        unsafe { &*(self as *const Self as *const LegacyPluginContext) }
+    }
+
+    /// Check that the header describes a layout this build can read. Newer
+    /// versions are accepted as long as they carry every field this build
+    /// knows about.
+    pub fn validate(&self) -> Result<()> {
+        if self.abi_version < 2 {
+            bail!(
+                "unsupported plugin ABI version {} (this build supports 2 to {})",
+                self.abi_version,
+                PLUGIN_ABI_VERSION
+            );
+        }
+        let expected = Self::size_for(self.abi_version);
+        if self.size < expected {
+            bail!(
+                "plugin context too small: {} bytes, expected at least {}",
+                self.size,
+                expected
+            );
+        }
+        Ok(())
+    }
+
+    /// Number of bytes a context of the given layout version occupies; for
+    /// versions newer than this build, at least the whole of `Self`.
+    fn size_for(version: u32) -> usize {
+        match version {
+            2 => offset_of!(Self, free_color),
+            3 => offset_of!(Self, network),
+            4 => offset_of!(Self, plain),
+            5 => offset_of!(Self, theme),
+            6 => offset_of!(Self, locale),
+            _ => size_of::<Self>(),
+        }
+    }
+}
+
+/// Exported so the host can find out which `PluginContext` layout a plugin
+/// was built against before calling into it.
+#[no_mangle]
+pub extern "C" fn reinze_plugin_abi() -> u32 {
+    PLUGIN_ABI_VERSION
+}
+
+/// Decide which context layout the host should hand to a plugin.
+/// `plugin_abi` is the result of `reinze_plugin_abi`, or `None` when the
+/// plugin does not export that symbol. A plugin newer than the host gets the
+/// host's own layout, which it reads as a prefix of its own.
+pub fn negotiate(plugin_abi: Option<u32>) -> Result<u32> {
+    match plugin_abi {
+        None => Ok(PLUGIN_ABI_VERSION_LEGACY),
+        Some(0) => bail!("plugin reported an invalid ABI version of 0"),
+        Some(v) => Ok(v.min(PLUGIN_ABI_VERSION)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    extern "C" fn stub_color(_host: *const c_char, _colors: *const c_char) -> ColorResult {
+        ColorResult::default()
+    }
+
+    #[test]
+    fn test_new_fills_header() {
+        let ctx = PluginContext::new(std::ptr::null(), std::ptr::null(), std::ptr::null(), stub_color);
+        assert_eq!(ctx.abi_version, PLUGIN_ABI_VERSION);
+        assert_eq!(ctx.size, size_of::<PluginContext>());
+        assert!(ctx.validate().is_ok());
+    }
+
+    #[test]
+    fn test_from_legacy() {
+        let cmd = CString::new("stats").unwrap();
+        let param = CString::new("zezima").unwrap();
+        let author = CString::new("nick!ident@host").unwrap();
+        let legacy = LegacyPluginContext {
+            cmd: cmd.as_ptr(),
+            param: param.as_ptr(),
+            author: author.as_ptr(),
+            color: stub_color,
+        };
+
+        let ctx = PluginContext::from_legacy(&legacy);
+        assert_eq!(ctx.cmd, cmd.as_ptr());
+        assert_eq!(ctx.param, param.as_ptr());
+        assert_eq!(ctx.author, author.as_ptr());
+        assert_eq!(ctx.abi_version, PLUGIN_ABI_VERSION);
+        assert!(ctx.validate().is_ok());
+    }
+
+    #[test]
+    fn test_legacy_is_prefix() {
+        let cmd = CString::new("ge").unwrap();
+        let ctx = PluginContext::new(cmd.as_ptr(), std::ptr::null(), std::ptr::null(), stub_color);
+        let legacy = ctx.as_legacy();
+        assert_eq!(legacy.cmd, cmd.as_ptr());
+        assert!(legacy.param.is_null());
+        assert!(size_of::<LegacyPluginContext>() < size_of::<PluginContext>());
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_header() {
+        let mut ctx = PluginContext::new(std::ptr::null(), std::ptr::null(), std::ptr::null(), stub_color);
+        ctx.abi_version = PLUGIN_ABI_VERSION + 1;
+        ctx.size = offset_of!(PluginContext, plain);
+        assert!(ctx.validate().is_err());
+
+        ctx.abi_version = 0;
+        assert!(ctx.validate().is_err());
+
+        ctx.abi_version = PLUGIN_ABI_VERSION;
+        ctx.size = size_of::<LegacyPluginContext>();
+        assert!(ctx.validate().is_err());
+    }
+
+    #[test]
+    fn test_version_2_context() {
+        let mut ctx = PluginContext::new(std::ptr::null(), std::ptr::null(), std::ptr::null(), stub_color);
+        ctx.abi_version = 2;
+        ctx.size = offset_of!(PluginContext, free_color);
+        assert!(ctx.validate().is_ok());
+        assert!(ctx.free_color().is_none());
+
+        let ctx = PluginContext::new(std::ptr::null(), std::ptr::null(), std::ptr::null(), stub_color);
+        assert!(ctx.free_color().is_some());
+    }
+
+    #[test]
+    fn test_version_3_context() {
+        let network = CString::new("Rizon").unwrap();
+        let channel = CString::new("#rs").unwrap();
+        let mut ctx = PluginContext::new(std::ptr::null(), std::ptr::null(), std::ptr::null(), stub_color)
+            .with_origin(network.as_ptr(), channel.as_ptr());
+        assert_eq!(ctx.origin(), (network.as_ptr(), channel.as_ptr()));
+
+        ctx.abi_version = 3;
+        ctx.size = offset_of!(PluginContext, network);
+        assert!(ctx.validate().is_ok());
+        assert!(ctx.free_color().is_some());
+        assert!(ctx.origin().0.is_null());
+    }
+
+    #[test]
+    fn test_version_4_context() {
+        let mut ctx = PluginContext::new(std::ptr::null(), std::ptr::null(), std::ptr::null(), stub_color)
+            .with_plain(true);
+        assert!(ctx.plain());
+
+        ctx.abi_version = 4;
+        ctx.size = offset_of!(PluginContext, plain);
+        assert!(ctx.validate().is_ok());
+        assert!(!ctx.plain());
+    }
+
+    #[test]
+    fn test_version_5_context() {
+        let theme = CString::new("positive=09").unwrap();
+        let mut ctx = PluginContext::new(std::ptr::null(), std::ptr::null(), std::ptr::null(), stub_color)
+            .with_plain(true)
+            .with_theme(theme.as_ptr());
+        assert_eq!(ctx.theme(), theme.as_ptr());
+        assert!(ctx.set_theme().is_some());
+
+        ctx.abi_version = 5;
+        ctx.size = offset_of!(PluginContext, theme);
+        assert!(ctx.validate().is_ok());
+        assert!(ctx.plain());
+        assert!(ctx.theme().is_null());
+        assert!(ctx.set_theme().is_none());
+    }
+
+    #[test]
+    fn test_version_6_context() {
+        let locale = CString::new("de").unwrap();
+        let mut ctx = PluginContext::new(std::ptr::null(), std::ptr::null(), std::ptr::null(), stub_color)
+            .with_locale(locale.as_ptr());
+        assert_eq!(ctx.locale(), locale.as_ptr());
+        assert!(ctx.set_locale().is_some());
+
+        ctx.abi_version = 6;
+        ctx.size = offset_of!(PluginContext, locale);
+        assert!(ctx.validate().is_ok());
+        assert!(ctx.set_theme().is_some());
+        assert!(ctx.locale().is_null());
+        assert!(ctx.set_locale().is_none());
+    }
+
+    #[test]
+    fn test_future_host_context() {
+        // A newer host appends a field this build doesn't know about.
+        #[repr(C)]
+        struct FutureContext {
+            base: PluginContext,
+            extra: u64,
+        }
+
+        let network = CString::new("Rizon").unwrap();
+        let mut future = FutureContext {
+            base: PluginContext::new(std::ptr::null(), std::ptr::null(), std::ptr::null(), stub_color)
+                .with_origin(network.as_ptr(), std::ptr::null())
+                .with_plain(true),
+            extra: 7,
+        };
+        future.base.abi_version = PLUGIN_ABI_VERSION + 1;
+        future.base.size = size_of::<FutureContext>();
+
+        assert!(future.base.validate().is_ok());
+        assert!(future.base.plain());
+        assert!(future.base.free_color().is_some());
+        assert_eq!(future.base.origin().0, network.as_ptr());
+        assert_eq!(future.extra, 7);
+    }
+
+    #[test]
+    fn test_for_version() {
+        let ctx = |version| PluginContext::for_version(std::ptr::null(), std::ptr::null(), std::ptr::null(), stub_color, version);
+
+        let v3 = ctx(3);
+        assert_eq!(v3.abi_version, 3);
+        assert_eq!(v3.size, offset_of!(PluginContext, network));
+        assert!(v3.validate().is_ok());
+        assert!(v3.free_color().is_some());
+        assert!(!v3.with_plain(true).plain());
+
+        assert_eq!(ctx(PLUGIN_ABI_VERSION + 1).abi_version, PLUGIN_ABI_VERSION);
+        assert_eq!(ctx(PLUGIN_ABI_VERSION).size, size_of::<PluginContext>());
+        assert_eq!(ctx(1).abi_version, 2);
+    }
+
+    #[test]
+    fn test_reinze_plugin_abi() {
+        assert_eq!(reinze_plugin_abi(), PLUGIN_ABI_VERSION);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate(None).unwrap(), PLUGIN_ABI_VERSION_LEGACY);
+        assert_eq!(negotiate(Some(1)).unwrap(), 1);
+        assert_eq!(negotiate(Some(PLUGIN_ABI_VERSION)).unwrap(), PLUGIN_ABI_VERSION);
+        assert!(negotiate(Some(0)).is_err());
+        assert_eq!(negotiate(Some(PLUGIN_ABI_VERSION + 1)).unwrap(), PLUGIN_ABI_VERSION);
+    }
+}
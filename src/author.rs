@@ -1,7 +1,8 @@
 pub mod cache;
 
+use crate::ffi::{reinze_free_color_result, ColorResultGuard};
 use crate::{ColorResult, Colors};
-use std::ffi::{CStr, CString};
+use std::ffi::CString;
 use std::os::raw::c_char;
 
 pub struct Author {
@@ -12,11 +13,17 @@ pub struct Author {
     #[allow(dead_code)]
     pub address: String,
     pub full: String,
-    pub color: extern "C" fn(*const c_char, *const c_char) -> ColorResult,
+    pub color: unsafe extern "C" fn(*const c_char, *const c_char) -> ColorResult,
+    /// How to release what `color` returns; `None` frees it locally, which
+    /// is only correct when the host shares this build's allocator.
+    pub free_color: Option<unsafe extern "C" fn(ColorResult)>,
+    /// Emit text without control codes. `color` always returns real codes,
+    /// so this comes from `PluginContext::plain` instead.
+    pub plain: bool,
 }
 
 impl Author {
-    pub fn create<T>(a: T, f: extern "C" fn(*const c_char, *const c_char) -> ColorResult) -> Self
+    pub fn create<T>(a: T, f: unsafe extern "C" fn(*const c_char, *const c_char) -> ColorResult) -> Self
     where
         T: ToString,
     {
@@ -28,7 +35,7 @@ impl Author {
             host = &replaced;
         }
 
-        let (ident, address) = host.split_once("@").unwrap_or(("", &host));
+        let (ident, address) = host.split_once("@").unwrap_or(("", host));
 
         Self {
             nick: nick.to_string(),
@@ -37,6 +44,8 @@ impl Author {
             address: address.to_string(),
             full: author.to_string(),
             color: f,
+            free_color: None,
+            plain: false,
         }
     }
 
@@ -44,18 +53,24 @@ impl Author {
     where
         T: ToString,
     {
-        let color = unsafe { self.colors() }.c1;
+        let colors = unsafe { self.colors() };
+        if colors.plain {
+            return s.to_string();
+        }
 
-        wrap(s.to_string().as_str(), &color)
+        wrap(s.to_string().as_str(), &colors.code1())
     }
 
     pub fn c2<T>(&self, s: T) -> String
     where
         T: ToString,
     {
-        let color = unsafe { self.colors() }.c2;
+        let colors = unsafe { self.colors() };
+        if colors.plain {
+            return s.to_string();
+        }
 
-        wrap(s.to_string().as_str(), &color)
+        wrap(s.to_string().as_str(), &colors.code2())
     }
 
     pub fn l<T>(&self, s: T) -> String
@@ -63,7 +78,10 @@ impl Author {
         T: ToString,
     {
         let colors = unsafe { self.colors() };
-        format!("{}{}{}", wrap("[", &colors.c1), wrap(&s.to_string(), &colors.c2), wrap("]", &colors.c1))
+        if colors.plain {
+            return format!("[{}]", s.to_string());
+        }
+        format!("{}{}{}", wrap("[", &colors.code1()), wrap(&s.to_string(), &colors.code2()), wrap("]", &colors.code1()))
     }
 
     pub fn p<T>(&self, s: T) -> String
@@ -71,32 +89,37 @@ impl Author {
         T: ToString,
     {
         let colors = unsafe { self.colors() };
-        format!("{}{}{}", wrap("(", &colors.c1), wrap(&s.to_string(), &colors.c2), wrap(")", &colors.c1))
+        if colors.plain {
+            return format!("({})", s.to_string());
+        }
+        format!("{}{}{}", wrap("(", &colors.code1()), wrap(&s.to_string(), &colors.code2()), wrap(")", &colors.code1()))
     }
 
+    /// # Safety
+    /// The `color` callback must return valid C strings, and `free_color`
+    /// (or this build's allocator, when it is `None`) must own them.
     pub unsafe fn colors(&self) -> Colors {
-        let host = CString::new(self.host.as_str()).unwrap().into_raw();
-        let empty = CString::new("").unwrap().into_raw();
-
-        let results = (self.color)(host, empty);
-
-        let c1 = CStr::from_ptr(results.c1).to_string_lossy().into_owned();
-        let c2 = CStr::from_ptr(results.c2).to_string_lossy().into_owned();
-
-        _ = CString::from_raw(host);
-        _ = CString::from_raw(empty);
-        _ = CString::from_raw(results.c1 as *mut c_char);
-        _ = CString::from_raw(results.c2 as *mut c_char);
-
-        Colors { c1, c2 }
+        let host = CString::new(self.host.as_str()).unwrap_or_default();
+        let empty = CString::default();
+
+        let results = (self.color)(host.as_ptr(), empty.as_ptr());
+        let guard = ColorResultGuard::new(results, self.free_color.unwrap_or(reinze_free_color_result));
+
+        Colors::from_codes(&guard.c1(), &guard.c2())
+            .unwrap_or_else(|e| {
+                log::error!("Invalid colors from host for {}: {}", self.host, e);
+                Colors::default()
+            })
+            .with_plain(self.plain)
     }
 
     pub fn set_colors(&self, colors: Colors) {
         cache::set(self.host.clone(), colors)
     }
 
+    /// Go back to the default colors; plain mode stays as it was.
     pub fn clear_colors(&self) {
-        self.set_colors(Colors::default())
+        cache::clear(self.host.clone())
     }
 }
 
@@ -213,12 +236,47 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_c1_c2_accept_non_str() {
         let author = Author::create("nick!ident@host", stub_color);
         assert_eq!(author.c1(42), "\x031442");
         assert_eq!(author.c2(3.14), "\x03043.14");
     }
 
+    extern "C" fn stub_color_bg(_host: *const std::os::raw::c_char, _colors: *const std::os::raw::c_char) -> ColorResult {
+        ColorResult::from(&Colors::default().with_background(crate::IrcColor::new(1).unwrap()))
+    }
+
+    #[test]
+    fn test_background_color() {
+        let author = Author::create("nick!ident@host", stub_color_bg);
+        assert_eq!(unsafe { author.colors() }.bg, Some(crate::IrcColor::new(1).unwrap()));
+        assert_eq!(author.l("Warn"), "\x0314,01[\x0304,01Warn\x0314,01]");
+    }
+
+    extern "C" fn stub_color_one_digit(_host: *const std::os::raw::c_char, _colors: *const std::os::raw::c_char) -> ColorResult {
+        let c1 = std::ffi::CString::new("3").unwrap().into_raw();
+        let c2 = std::ffi::CString::new("4").unwrap().into_raw();
+        ColorResult { c1, c2 }
+    }
+
+    #[test]
+    fn test_one_digit_colors_normalized() {
+        let author = Author::create("nick!ident@host", stub_color_one_digit);
+        assert_eq!(author.c2(5), "\x03045");
+        assert_eq!(author.l(1), "\x0303[\x03041\x0303]");
+    }
+
+    #[test]
+    fn test_plain_mode() {
+        let mut author = Author::create("nick!ident@host", stub_color);
+        author.plain = true;
+        assert_eq!(author.c1("text"), "text");
+        assert_eq!(author.c2(42), "42");
+        assert_eq!(author.l("Stats"), "[Stats]");
+        assert_eq!(author.p("Info"), "(Info)");
+    }
+
     // Note: set_colors/clear_colors require a database connection and
     // are tested via integration tests rather than unit tests.
 }
@@ -1,4 +1,4 @@
-use crate::{database, ColorResult, Colors};
+use crate::{database, preset, ColorResult, Colors, IrcColor};
 use arc_swap::ArcSwap;
 use mysql::params;
 use mysql::prelude::Queryable;
@@ -48,14 +48,16 @@ fn get_from_db(author_host: String) -> Colors {
         }
     };
 
-    match conn.exec_first::<(String, String), &str, mysql::Params>(
-        "SELECT color1, color2 FROM colors WHERE host = :author_host",
+    match conn.exec_first::<(String, String, Option<u8>, bool), &str, mysql::Params>(
+        "SELECT color1, color2, bg, plain FROM colors WHERE host = :author_host",
         params! { author_host },
     ) {
-        Ok(Some(colors)) => Colors {
-            c1: colors.0,
-            c2: colors.1,
-        },
+        Ok(Some((color1, color2, bg, plain))) => from_row(&color1, &color2, bg)
+            .unwrap_or_else(|e| {
+                log::error!("Invalid colors stored in database: {}", e);
+                Colors::default()
+            })
+            .with_plain(plain),
         Ok(None) => Colors::default(),
         Err(e) => {
             log::error!("Error querying database: {}", e);
@@ -64,6 +66,15 @@ fn get_from_db(author_host: String) -> Colors {
     }
 }
 
+// The background has its own column; `color1`/`color2` hold two-digit codes.
+fn from_row(color1: &str, color2: &str, bg: Option<u8>) -> anyhow::Result<Colors> {
+    let colors = Colors::from_codes(color1, color2)?;
+    match bg {
+        Some(bg) => Ok(colors.with_background(IrcColor::new(bg)?)),
+        None => Ok(colors),
+    }
+}
+
 pub fn upsert_color(author_host: String, color: Colors) {
     let cache = COLOR_CACHE.get().expect("COLOR_CACHE not initialized");
 
@@ -85,11 +96,19 @@ pub fn set(author_host: String, colors: Colors) {
         }
     };
 
-    let _ = conn.exec::<bool, &str, mysql::Params>(
-        "INSERT INTO colors (host, color1, color2) VALUES (:author_host, :c1, :c2) \
-         ON DUPLICATE KEY UPDATE color1 = :c1, color2 = :c2",
-        params! { "author_host" => author_host, "c1" => colors.c1, "c2" => colors.c2 },
-    );
+    if let Err(e) = conn.exec_drop(
+        "INSERT INTO colors (host, color1, color2, bg, plain) VALUES (:author_host, :c1, :c2, :bg, :plain) \
+         ON DUPLICATE KEY UPDATE color1 = :c1, color2 = :c2, bg = :bg, plain = :plain",
+        params! {
+            "author_host" => author_host,
+            "c1" => colors.c1.to_string(),
+            "c2" => colors.c2.to_string(),
+            "bg" => colors.bg.map(IrcColor::value),
+            "plain" => colors.plain,
+        },
+    ) {
+        log::error!("Error saving colors: {}", e);
+    }
 }
 
 /// Cache-only upsert + read for testing without DB.
@@ -101,22 +120,52 @@ fn cache_get(author_host: &str) -> Option<Colors> {
     map.get(author_host).cloned()
 }
 
-pub extern "C" fn color_ffi(host: *const c_char, to_store: *const c_char) -> ColorResult {
-    let hostname = unsafe { CStr::from_ptr(host) }.to_str().unwrap_or_default();
-    let colors = unsafe { CStr::from_ptr(to_store) }.to_str().unwrap_or_default();
+/// Prefix for picking a named palette, e.g. `preset:ocean`.
+pub const PRESET_PREFIX: &str = "preset:";
+
+/// Passed to `color_ffi` to turn plain (no control codes) mode on.
+pub const PLAIN_SENTINEL: &str = "plain";
+
+/// Passed to `color_ffi` to turn plain mode back off, keeping the colors.
+pub const COLOR_SENTINEL: &str = "color";
+
+/// The `color` callback handed to plugins: reads the colors for `host`, or
+/// stores `to_store` first when it is not empty.
+///
+/// # Safety
+/// `host` and `to_store` must point to valid nul-terminated strings. The
+/// result must be released with `reinze_free_color_result`.
+pub unsafe extern "C" fn color_ffi(host: *const c_char, to_store: *const c_char) -> ColorResult {
+    let hostname = CStr::from_ptr(host).to_str().unwrap_or_default();
+    let colors = CStr::from_ptr(to_store).to_str().unwrap_or_default();
 
     if colors.is_empty() {
         let colors = get(hostname);
         ColorResult::from(&colors)
-    } else {
-        let Some((color1, color2)) = colors.split_once(",") else {
-            log::error!("color_ffi: invalid color format (expected 'c1,c2'): {:?}", colors);
-            return ColorResult::default();
+    } else if colors.eq_ignore_ascii_case(PLAIN_SENTINEL) || colors.eq_ignore_ascii_case(COLOR_SENTINEL) {
+        let colors = get(hostname).with_plain(colors.eq_ignore_ascii_case(PLAIN_SENTINEL));
+        set(hostname.to_string(), colors.clone());
+
+        ColorResult::from(&colors)
+    } else if let Some(name) = colors.strip_prefix(PRESET_PREFIX) {
+        let colors = match preset_colors(hostname, name) {
+            Ok(colors) => colors,
+            Err(e) => {
+                log::error!("color_ffi: {}", e);
+                return ColorResult::from(&get(hostname));
+            }
         };
 
-        let colors = Colors {
-            c1: color1.to_string(),
-            c2: color2.to_string(),
+        set(hostname.to_string(), colors.clone());
+
+        ColorResult::from(&colors)
+    } else {
+        let colors = match explicit_colors(hostname, colors) {
+            Ok(colors) => colors,
+            Err(e) => {
+                log::error!("color_ffi: rejected {:?}: {}", colors, e);
+                return ColorResult::from(&get(hostname));
+            }
         };
 
         set(hostname.to_string(), colors.clone());
@@ -125,9 +174,41 @@ pub extern "C" fn color_ffi(host: *const c_char, to_store: *const c_char) -> Col
     }
 }
 
+/// Reset `author_host` to the default colors, keeping their plain setting.
+pub fn clear(author_host: String) {
+    let colors = keep_plain(&author_host, Colors::default());
+    set(author_host, colors)
+}
+
+/// The colors `name` gives `hostname`, keeping their plain setting.
+fn preset_colors(hostname: &str, name: &str) -> anyhow::Result<Colors> {
+    let preset = preset::find(name)?;
+    Ok(keep_plain(hostname, preset.colors()))
+}
+
+/// Parse `c1,c2[,bg]` for `hostname`, keeping their plain setting.
+fn explicit_colors(hostname: &str, input: &str) -> anyhow::Result<Colors> {
+    let Some((color1, rest)) = input.split_once(",") else {
+        anyhow::bail!("invalid color format (expected 'c1,c2[,bg]' or 'preset:name')");
+    };
+
+    let colors = match rest.split_once(",") {
+        Some((color2, bg)) => Colors::from_codes(color1, color2)?.with_background(bg.parse()?),
+        None => Colors::from_codes(color1, rest)?,
+    };
+    Ok(keep_plain(hostname, colors))
+}
+
+// Plain mode is an accessibility setting, so picking or clearing colors
+// leaves it alone; only the plain/color sentinels change it.
+fn keep_plain(hostname: &str, colors: Colors) -> Colors {
+    colors.with_plain(get(hostname).plain)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ffi::{reinze_free_color_result, ColorResultGuard};
     use std::ffi::CString;
 
     // Tests run in the same process, and OnceLock can only be set once.
@@ -146,27 +227,31 @@ mod tests {
         upsert_color(
             "user@host.com".to_string(),
             Colors {
-                c1: "03".to_string(),
-                c2: "07".to_string(),
+                c1: "03".parse().unwrap(),
+                c2: "07".parse().unwrap(),
+                bg: None,
+                plain: false,
             },
         );
 
         let cached = cache_get("user@host.com").unwrap();
-        assert_eq!(cached.c1, "03");
-        assert_eq!(cached.c2, "07");
+        assert_eq!(cached.c1, IrcColor::new(3).unwrap());
+        assert_eq!(cached.c2, IrcColor::new(7).unwrap());
 
         // Overwrite
         upsert_color(
             "user@host.com".to_string(),
             Colors {
-                c1: "05".to_string(),
-                c2: "09".to_string(),
+                c1: "05".parse().unwrap(),
+                c2: "09".parse().unwrap(),
+                bg: None,
+                plain: false,
             },
         );
 
         let cached = cache_get("user@host.com").unwrap();
-        assert_eq!(cached.c1, "05");
-        assert_eq!(cached.c2, "09");
+        assert_eq!(cached.c1, IrcColor::new(5).unwrap());
+        assert_eq!(cached.c2, IrcColor::new(9).unwrap());
     }
 
     #[test]
@@ -176,23 +261,27 @@ mod tests {
         upsert_color(
             "alice@a.com".to_string(),
             Colors {
-                c1: "01".to_string(),
-                c2: "02".to_string(),
+                c1: "01".parse().unwrap(),
+                c2: "02".parse().unwrap(),
+                bg: None,
+                plain: false,
             },
         );
         upsert_color(
             "bob@b.com".to_string(),
             Colors {
-                c1: "03".to_string(),
-                c2: "04".to_string(),
+                c1: "03".parse().unwrap(),
+                c2: "04".parse().unwrap(),
+                bg: None,
+                plain: false,
             },
         );
 
         let alice = cache_get("alice@a.com").unwrap();
         let bob = cache_get("bob@b.com").unwrap();
 
-        assert_eq!(alice.c1, "01");
-        assert_eq!(bob.c1, "03");
+        assert_eq!(alice.c1, IrcColor::new(1).unwrap());
+        assert_eq!(bob.c1, IrcColor::new(3).unwrap());
         // Both should still be present
         assert!(cache_get("alice@a.com").is_some());
         assert!(cache_get("bob@b.com").is_some());
@@ -207,8 +296,10 @@ mod tests {
             upsert_color(
                 format!("host{}", i),
                 Colors {
-                    c1: format!("{:02}", i),
-                    c2: format!("{:02}", i + 10),
+                    c1: format!("{:02}", i).parse().unwrap(),
+                    c2: format!("{:02}", i + 10).parse().unwrap(),
+                    bg: None,
+                    plain: false,
                 },
             );
         }
@@ -216,15 +307,17 @@ mod tests {
         // All entries should be present
         for i in 0..10 {
             let cached = cache_get(&format!("host{}", i)).unwrap();
-            assert_eq!(cached.c1, format!("{:02}", i));
+            assert_eq!(cached.c1.value(), i);
         }
     }
 
     #[test]
     fn test_color_result_from_colors() {
         let colors = Colors {
-            c1: "14".to_string(),
-            c2: "04".to_string(),
+            c1: "14".parse().unwrap(),
+            c2: "04".parse().unwrap(),
+            bg: None,
+            plain: false,
         };
         let result = ColorResult::from(&colors);
 
@@ -255,4 +348,59 @@ mod tests {
             _ = CString::from_raw(result.c2 as *mut c_char);
         }
     }
+
+    #[test]
+    fn test_color_ffi_rejects_invalid() {
+        let _ = COLOR_CACHE.set(Arc::new(ArcSwap::from_pointee(HashMap::new())));
+        upsert_color("picky@host.com".to_string(), Colors::from_codes("03", "07").unwrap());
+
+        let host = CString::new("picky@host.com").unwrap();
+        for bad in ["red,04", "999,04", "03,07,abc", "03", "preset:neon"] {
+            let input = CString::new(bad).unwrap();
+            let guard = unsafe { ColorResultGuard::new(color_ffi(host.as_ptr(), input.as_ptr()), reinze_free_color_result) };
+            assert_eq!((guard.c1(), guard.c2()), ("03".to_string(), "07".to_string()), "{}", bad);
+        }
+
+        assert_eq!(cache_get("picky@host.com").unwrap().c1, IrcColor::new(3).unwrap());
+    }
+
+    #[test]
+    fn test_from_row() {
+        let colors = from_row("03", "07", Some(1)).unwrap();
+        assert_eq!(colors.code1(), "03,01");
+        assert_eq!(colors.code2(), "07,01");
+        assert_eq!(from_row("03", "07", None).unwrap().bg, None);
+        assert!(from_row("03", "07", Some(99)).is_err());
+    }
+
+    #[test]
+    fn test_explicit_colors_keep_plain() {
+        let _ = COLOR_CACHE.set(Arc::new(ArcSwap::from_pointee(HashMap::new())));
+        upsert_color("reader@host.com".to_string(), Colors::default().with_plain(true));
+
+        let colors = explicit_colors("reader@host.com", "3,7").unwrap();
+        assert!(colors.plain);
+        assert_eq!(colors.code2(), "07");
+        assert_eq!(explicit_colors("reader@host.com", "3,7,1").unwrap().code1(), "03,01");
+        assert!(explicit_colors("reader@host.com", "3").is_err());
+    }
+
+    #[test]
+    fn test_clear_keeps_plain() {
+        let _ = COLOR_CACHE.set(Arc::new(ArcSwap::from_pointee(HashMap::new())));
+        upsert_color("cleared@host.com".to_string(), Colors::from_codes("03", "07").unwrap().with_plain(true));
+
+        assert_eq!(keep_plain("cleared@host.com", Colors::default()), Colors::default().with_plain(true));
+    }
+
+    #[test]
+    fn test_preset_keeps_plain() {
+        let _ = COLOR_CACHE.set(Arc::new(ArcSwap::from_pointee(HashMap::new())));
+        upsert_color("quiet@host.com".to_string(), Colors::from_codes("03", "07").unwrap().with_plain(true));
+
+        let colors = preset_colors("quiet@host.com", "ocean").unwrap();
+        assert!(colors.plain);
+        assert_eq!(colors.c1, IrcColor::new(10).unwrap());
+        assert!(preset_colors("quiet@host.com", "neon").is_err());
+    }
 }
@@ -0,0 +1,124 @@
+use crate::ColorResult;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+/// Release a `ColorResult` created by `ColorResult::from` in this library.
+///
+/// # Safety
+/// Both strings must be null or have come from `ColorResult::from` in this
+/// build, and must not be used or freed again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn reinze_free_color_result(result: ColorResult) {
+    reinze_free_string(result.c1 as *mut c_char);
+    reinze_free_string(result.c2 as *mut c_char);
+}
+
+/// Release a string created by `CString::into_raw` in this library.
+///
+/// # Safety
+/// `ptr` must be null or have come from `CString::into_raw` in this build,
+/// and must not be used or freed again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn reinze_free_string(ptr: *mut c_char) {
+    if ptr.is_null() {
+        return;
+    }
+
+    _ = CString::from_raw(ptr);
+}
+
+/// Owns a `ColorResult` received from the host and hands it back to the
+/// matching free function when dropped.
+pub struct ColorResultGuard {
+    result: Option<ColorResult>,
+    free: unsafe extern "C" fn(ColorResult),
+}
+
+impl ColorResultGuard {
+    /// # Safety
+    /// `result` must hold null or valid C strings that `free` can release,
+    /// and nothing else may free them.
+    pub unsafe fn new(result: ColorResult, free: unsafe extern "C" fn(ColorResult)) -> Self {
+        Self {
+            result: Some(result),
+            free,
+        }
+    }
+
+    pub fn c1(&self) -> String {
+        self.result.as_ref().map_or_else(String::new, |r| read(r.c1))
+    }
+
+    pub fn c2(&self) -> String {
+        self.result.as_ref().map_or_else(String::new, |r| read(r.c2))
+    }
+}
+
+impl Drop for ColorResultGuard {
+    fn drop(&mut self) {
+        if let Some(result) = self.result.take() {
+            // Safe because `new` requires `free` to own `result`.
+            unsafe { (self.free)(result) };
+        }
+    }
+}
+
+fn read(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+
+    unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Colors;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static FREED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn counting_free(result: ColorResult) {
+        FREED.fetch_add(1, Ordering::SeqCst);
+        reinze_free_color_result(result);
+    }
+
+    #[test]
+    fn test_guard_reads_and_frees() {
+        let before = FREED.load(Ordering::SeqCst);
+        {
+            let guard = unsafe { ColorResultGuard::new(ColorResult::default(), counting_free) };
+            assert_eq!(guard.c1(), "14");
+            assert_eq!(guard.c2(), "04");
+        }
+        assert_eq!(FREED.load(Ordering::SeqCst), before + 1);
+    }
+
+    #[test]
+    fn test_guard_null_strings() {
+        let result = ColorResult {
+            c1: std::ptr::null(),
+            c2: std::ptr::null(),
+        };
+        let guard = unsafe { ColorResultGuard::new(result, reinze_free_color_result) };
+        assert_eq!(guard.c1(), "");
+        assert_eq!(guard.c2(), "");
+    }
+
+    #[test]
+    fn test_free_string_null() {
+        unsafe { reinze_free_string(std::ptr::null_mut()) };
+    }
+
+    #[test]
+    fn test_free_color_result() {
+        let colors = Colors {
+            c1: "03".parse().unwrap(),
+            c2: "07".parse().unwrap(),
+            bg: None,
+            plain: false,
+        };
+        unsafe { reinze_free_color_result(ColorResult::from(&colors)) };
+    }
+}
@@ -0,0 +1,545 @@
+use crate::commas_int;
+use crate::theme::Theme;
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::str::FromStr;
+
+const OSRS_SKILLS: [&str; 25] = [
+    "Overall",
+    "Attack",
+    "Defence",
+    "Strength",
+    "Hitpoints",
+    "Ranged",
+    "Prayer",
+    "Magic",
+    "Cooking",
+    "Woodcutting",
+    "Fletching",
+    "Fishing",
+    "Firemaking",
+    "Crafting",
+    "Smithing",
+    "Mining",
+    "Herblore",
+    "Agility",
+    "Thieving",
+    "Slayer",
+    "Farming",
+    "Runecraft",
+    "Hunter",
+    "Construction",
+    "Sailing",
+];
+
+const RS3_SKILLS: [&str; 30] = [
+    "Overall",
+    "Attack",
+    "Defence",
+    "Strength",
+    "Constitution",
+    "Ranged",
+    "Prayer",
+    "Magic",
+    "Cooking",
+    "Woodcutting",
+    "Fletching",
+    "Fishing",
+    "Firemaking",
+    "Crafting",
+    "Smithing",
+    "Mining",
+    "Herblore",
+    "Agility",
+    "Thieving",
+    "Slayer",
+    "Farming",
+    "Runecrafting",
+    "Hunter",
+    "Construction",
+    "Summoning",
+    "Dungeoneering",
+    "Divination",
+    "Invention",
+    "Archaeology",
+    "Necromancy",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Game {
+    Osrs,
+    Rs3,
+}
+
+impl Game {
+    /// The name snapshots are stored under.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Game::Osrs => "osrs",
+            Game::Rs3 => "rs3",
+        }
+    }
+
+    pub fn skill_names(&self) -> &'static [&'static str] {
+        match self {
+            Game::Osrs => &OSRS_SKILLS,
+            Game::Rs3 => &RS3_SKILLS,
+        }
+    }
+}
+
+impl FromStr for Game {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "osrs" | "oldschool" | "07" => Ok(Game::Osrs),
+            "rs3" | "rs" => Ok(Game::Rs3),
+            _ => bail!("unknown game {:?}, use osrs or rs3", s),
+        }
+    }
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// One skill line: `rank,level,xp`. `None` is the `-1` the hiscores send
+/// for unranked entries; the level is usually a placeholder `1` instead, but
+/// can be `-1` as well.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Skill {
+    pub name: String,
+    pub rank: Option<u64>,
+    pub level: Option<u32>,
+    pub xp: Option<u64>,
+}
+
+/// One activity, boss or minigame line: `rank,score`. Activities are
+/// identified by their position, since the list changes with game updates.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Activity {
+    pub index: usize,
+    pub rank: Option<u64>,
+    pub score: Option<u64>,
+}
+
+/// A player's hiscores as returned by `index_lite`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hiscores {
+    pub game: Game,
+    pub skills: Vec<Skill>,
+    pub activities: Vec<Activity>,
+}
+
+impl Hiscores {
+    /// Parse the `index_lite` CSV: one `rank,level,xp` line per skill,
+    /// starting with Overall, then one `rank,score` line per activity.
+    /// Skills beyond the ones this crate knows about are named by position.
+    pub fn parse(game: Game, csv: &str) -> Result<Self> {
+        let mut skills = Vec::new();
+        let mut activities = Vec::new();
+
+        for (i, line) in csv.lines().map(str::trim).enumerate().filter(|(_, l)| !l.is_empty()) {
+            let fields: Vec<&str> = line.split(',').collect();
+            let value = |n: usize| {
+                parse_value(fields[n]).with_context(|| format!("invalid hiscores line {}: {:?}", i + 1, line))
+            };
+
+            match fields.len() {
+                3 if activities.is_empty() => {
+                    let name = game
+                        .skill_names()
+                        .get(skills.len())
+                        .map_or_else(|| format!("Skill {}", skills.len()), |name| name.to_string());
+                    skills.push(Skill {
+                        name,
+                        rank: value(0)?,
+                        level: parse_value(fields[1])
+                            .with_context(|| format!("invalid level on hiscores line {}: {:?}", i + 1, line))?,
+                        xp: value(2)?,
+                    });
+                }
+                3 => bail!("hiscores line {} is a skill after the activities: {:?}", i + 1, line),
+                2 => activities.push(Activity {
+                    index: activities.len(),
+                    rank: value(0)?,
+                    score: value(1)?,
+                }),
+                _ => bail!("invalid hiscores line {}: {:?}", i + 1, line),
+            }
+        }
+
+        if skills.is_empty() {
+            bail!("hiscores data has no skills");
+        }
+
+        Ok(Self {
+            game,
+            skills,
+            activities,
+        })
+    }
+
+    /// Write the hiscores back out in `index_lite` form.
+    pub fn to_csv(&self) -> String {
+        let skills = self
+            .skills
+            .iter()
+            .map(|s| format!("{},{},{}", write_value(s.rank), write_value(s.level), write_value(s.xp)));
+        let activities = self
+            .activities
+            .iter()
+            .map(|a| format!("{},{}", write_value(a.rank), write_value(a.score)));
+
+        skills.chain(activities).map(|line| line + "\n").collect()
+    }
+
+    /// Look up a skill by name, ignoring case.
+    pub fn skill(&self, name: &str) -> Option<&Skill> {
+        self.skills.iter().find(|s| s.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn overall(&self) -> &Skill {
+        &self.skills[0]
+    }
+
+    pub fn activity(&self, index: usize) -> Option<&Activity> {
+        self.activities.get(index)
+    }
+
+    /// What changed since `older`. Entries that were unranked before count
+    /// from zero and are marked `newly_ranked`, with no level change since
+    /// their old level was only a placeholder; entries that didn't change
+    /// are left out. Skills and activities are sorted by largest gain.
+    pub fn diff(&self, older: &Hiscores) -> Gains {
+        let skill_gain = |skill: &Skill| {
+            let before = older.skill(&skill.name);
+            SkillGain {
+                name: skill.name.clone(),
+                xp: change(before.and_then(|b| b.xp), skill.xp),
+                levels: match (ranked_level(Some(skill)), ranked_level(before)) {
+                    (Some(level), Some(old)) => i64::from(level) - i64::from(old),
+                    // Off the hiscores now or before, so one level is only a placeholder.
+                    _ => 0,
+                },
+                rank: rank_change(before.and_then(|b| b.rank), skill.rank),
+                newly_ranked: skill.rank.is_some() && before.is_none_or(|b| b.rank.is_none()),
+            }
+        };
+
+        let mut skills: Vec<SkillGain> = self
+            .skills
+            .iter()
+            .skip(1)
+            .map(skill_gain)
+            .filter(|g| g.xp != 0 || g.levels != 0)
+            .collect();
+        skills.sort_by(|a, b| b.xp.cmp(&a.xp).then(b.levels.cmp(&a.levels)));
+
+        let mut activities: Vec<ActivityGain> = self
+            .activities
+            .iter()
+            .map(|activity| {
+                let before = older.activity(activity.index);
+                ActivityGain {
+                    index: activity.index,
+                    score: change(before.and_then(|b| b.score), activity.score),
+                    rank: rank_change(before.and_then(|b| b.rank), activity.rank),
+                    newly_ranked: activity.rank.is_some() && before.is_none_or(|b| b.rank.is_none()),
+                }
+            })
+            .filter(|g| g.score != 0)
+            .collect();
+        activities.sort_by_key(|g| std::cmp::Reverse(g.score));
+
+        Gains {
+            overall: skill_gain(self.overall()),
+            skills,
+            activities,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SkillGain {
+    pub name: String,
+    pub xp: i64,
+    pub levels: i64,
+    /// Places climbed; negative if the player dropped. `None` unless ranked
+    /// both times.
+    pub rank: Option<i64>,
+    pub newly_ranked: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActivityGain {
+    pub index: usize,
+    pub score: i64,
+    pub rank: Option<i64>,
+    pub newly_ranked: bool,
+}
+
+/// The result of `Hiscores::diff`. `skills` leaves out Overall, which is
+/// always in `overall`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gains {
+    pub overall: SkillGain,
+    pub skills: Vec<SkillGain>,
+    pub activities: Vec<ActivityGain>,
+}
+
+impl Gains {
+    pub fn is_empty(&self) -> bool {
+        self.overall.xp == 0 && self.skills.is_empty() && self.activities.is_empty()
+    }
+
+    /// The standard gains line: `[Overall] +1,234 (+2) | [Attack] +1,000 |
+    /// [Zulrah] +17 (new)`, in `theme`'s colors. Activities are named from
+    /// `activity_names` by position, the way the plugin lists them.
+    pub fn line(&self, theme: &Theme, activity_names: &[&str]) -> String {
+        if self.is_empty() {
+            return theme.value("No gains");
+        }
+
+        let entry = |name: &str, amount: i64, levels: i64, newly_ranked: bool| {
+            let mut entry = format!("{} {}", theme.l(name), theme.delta(amount));
+            if levels != 0 {
+                entry.push_str(&format!(" {}", theme.p(signed(levels))));
+            }
+            if newly_ranked {
+                entry.push_str(&format!(" {}", theme.p("new")));
+            }
+            entry
+        };
+
+        let overall = std::iter::once(&self.overall)
+            .filter(|g| g.xp != 0)
+            .chain(self.skills.iter())
+            .map(|g| entry(&g.name, g.xp, g.levels, g.newly_ranked));
+        let activities = self.activities.iter().map(|g| {
+            let name = activity_names
+                .get(g.index)
+                .map_or_else(|| format!("Activity {}", g.index + 1), |name| name.to_string());
+            entry(&name, g.score, 0, g.newly_ranked)
+        });
+
+        theme.join(&overall.chain(activities).collect::<Vec<_>>())
+    }
+}
+
+// Unranked before counts as zero; unranked now (a reset, or a drop off the
+// hiscores) counts as no change.
+fn change(before: Option<u64>, after: Option<u64>) -> i64 {
+    match after {
+        Some(after) => after as i64 - before.unwrap_or(0) as i64,
+        None => 0,
+    }
+}
+
+fn rank_change(before: Option<u64>, after: Option<u64>) -> Option<i64> {
+    Some(before? as i64 - after? as i64)
+}
+
+fn signed(n: i64) -> String {
+    if n > 0 {
+        format!("+{}", commas_int(n))
+    } else {
+        commas_int(n)
+    }
+}
+
+// The hiscores use -1 for "not ranked".
+fn parse_value<T>(s: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match s.trim() {
+        "-1" => Ok(None),
+        s => Ok(Some(s.parse()?)),
+    }
+}
+
+/// The level of a skill that is on the hiscores; unranked skills only have
+/// a placeholder.
+fn ranked_level(skill: Option<&Skill>) -> Option<u32> {
+    skill.filter(|s| s.xp.is_some() && s.rank.is_some()).and_then(|s| s.level)
+}
+
+fn write_value<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "-1".to_string(), |v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn osrs_csv() -> String {
+        let mut csv = String::from("1234,1500,50000000\n");
+        csv.push_str("-1,1,-1\n");
+        for _ in 2..OSRS_SKILLS.len() {
+            csv.push_str("5000,70,737627\n");
+        }
+        csv.push_str("-1,-1\n42,17\n");
+        csv
+    }
+
+    #[test]
+    fn test_parse_osrs() {
+        let hiscores = Hiscores::parse(Game::Osrs, &osrs_csv()).unwrap();
+        assert_eq!(hiscores.skills.len(), OSRS_SKILLS.len());
+        assert_eq!(hiscores.activities.len(), 2);
+
+        assert_eq!(hiscores.overall().xp, Some(50_000_000));
+        assert_eq!(hiscores.overall().level, Some(1500));
+
+        let attack = hiscores.skill("attack").unwrap();
+        assert_eq!((attack.rank, attack.level, attack.xp), (None, Some(1), None));
+        assert_eq!(hiscores.skill("Sailing").unwrap().level, Some(70));
+
+        assert_eq!(hiscores.activity(0).unwrap().score, None);
+        assert_eq!(hiscores.activity(1).unwrap().rank, Some(42));
+        assert_eq!(hiscores.activity(1).unwrap().score, Some(17));
+    }
+
+    #[test]
+    fn test_parse_rs3() {
+        let csv = "1,2898,5600000000\r\n2,99,200000000\r\n\r\n100,5\r\n";
+        let hiscores = Hiscores::parse(Game::Rs3, csv).unwrap();
+        assert_eq!(hiscores.skills[1].name, "Attack");
+        assert_eq!(hiscores.overall().xp, Some(5_600_000_000));
+        assert_eq!(hiscores.activities.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_unranked_level() {
+        let hiscores = Hiscores::parse(Game::Rs3, "-1,-1,-1").unwrap();
+        assert_eq!(hiscores.overall().level, None);
+        assert_eq!(hiscores.to_csv(), "-1,-1,-1\n");
+    }
+
+    #[test]
+    fn test_unknown_skills_named_by_position() {
+        let mut csv = String::new();
+        for _ in 0..=OSRS_SKILLS.len() {
+            csv.push_str("1,1,1\n");
+        }
+        let hiscores = Hiscores::parse(Game::Osrs, &csv).unwrap();
+        assert_eq!(hiscores.skills.last().unwrap().name, format!("Skill {}", OSRS_SKILLS.len()));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let csv = osrs_csv();
+        let hiscores = Hiscores::parse(Game::Osrs, &csv).unwrap();
+        assert_eq!(hiscores.to_csv(), csv);
+        assert_eq!(Hiscores::parse(Game::Osrs, &hiscores.to_csv()).unwrap(), hiscores);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Hiscores::parse(Game::Osrs, "").is_err());
+        assert!(Hiscores::parse(Game::Osrs, "1,2\n").is_err());
+        assert!(Hiscores::parse(Game::Osrs, "1,2,3,4\n").is_err());
+        assert!(Hiscores::parse(Game::Osrs, "1,x,3\n").is_err());
+        assert!(Hiscores::parse(Game::Osrs, "1,1,-5\n").is_err());
+        assert!(Hiscores::parse(Game::Osrs, "<html>Not found</html>").is_err());
+
+        assert!(Hiscores::parse(Game::Osrs, "1,-5,1\n").is_err());
+
+        let err = Hiscores::parse(Game::Osrs, "1,1,1\n1,1\n1,1,1\n").unwrap_err().to_string();
+        assert!(err.contains("line 3"), "{}", err);
+    }
+
+    #[test]
+    fn test_game() {
+        assert_eq!("OSRS".parse::<Game>().unwrap(), Game::Osrs);
+        assert_eq!("rs3".parse::<Game>().unwrap(), Game::Rs3);
+        assert!("wow".parse::<Game>().is_err());
+        assert_eq!(Game::Rs3.to_string(), "rs3");
+    }
+
+    fn hiscores(lines: &[&str]) -> Hiscores {
+        Hiscores::parse(Game::Osrs, &lines.join("\n")).unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let older = hiscores(&["100,50,1000", "500,10,1154", "900,5,388", "-1,1,-1", "10,5", "-1,-1"]);
+        let newer = hiscores(&["90,60,101500", "450,12,1500", "900,5,388", "8000,40,100000", "9,12", "77,3"]);
+        let gains = newer.diff(&older);
+
+        assert_eq!(gains.overall.xp, 100_500);
+        assert_eq!(gains.overall.levels, 10);
+        assert_eq!(gains.overall.rank, Some(10));
+
+        // Defence didn't change; Strength was unranked and counts from zero.
+        let names: Vec<&str> = gains.skills.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, vec!["Strength", "Attack"]);
+        assert_eq!(gains.skills[0].xp, 100_000);
+        assert!(gains.skills[0].newly_ranked);
+        assert_eq!(gains.skills[0].levels, 0);
+        assert_eq!(gains.skills[0].rank, None);
+        assert_eq!(gains.skills[1].xp, 346);
+        assert_eq!(gains.skills[1].levels, 2);
+        assert_eq!(gains.skills[1].rank, Some(50));
+        assert!(!gains.skills[1].newly_ranked);
+
+        assert_eq!(gains.activities.len(), 2);
+        assert_eq!((gains.activities[0].index, gains.activities[0].score), (0, 7));
+        assert_eq!((gains.activities[1].index, gains.activities[1].score), (1, 3));
+        assert!(gains.activities[1].newly_ranked);
+    }
+
+    #[test]
+    fn test_diff_dropped_off_hiscores() {
+        let older = hiscores(&["1,10,100", "5,5,400"]);
+        let newer = hiscores(&["1,10,100", "-1,5,-1"]);
+        let gains = newer.diff(&older);
+        assert!(gains.is_empty());
+        assert_eq!(gains.line(&Theme::default(), &[]), Theme::default().value("No gains"));
+    }
+
+    #[test]
+    fn test_diff_unranked_level_is_not_a_loss() {
+        // Unranked skills come back as level 1, which isn't a drop of 49.
+        let older = hiscores(&["1,60,5000", "5,50,4000", "8,10,1154"]);
+        let newer = hiscores(&["1,60,5100", "-1,1,-1", "8,11,1254"]);
+        let gains = newer.diff(&older);
+
+        let names: Vec<&str> = gains.skills.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, vec!["Defence"]);
+        assert_eq!(gains.skills[0].levels, 1);
+        assert!(!gains.line(&Theme::default(), &[]).contains("-49"));
+    }
+
+    #[test]
+    fn test_gains_line() {
+        let older = hiscores(&["100,50,1000", "500,10,1154", "-1,-1"]);
+        let newer = hiscores(&["100,52,2500", "500,12,2654", "77,3"]);
+        let theme = Theme::default();
+        let line = newer.diff(&older).line(&theme, &["Zulrah"]);
+
+        let expected = [
+            format!("{} {} {}", theme.l("Overall"), theme.positive("+1,500"), theme.p("+2")),
+            format!("{} {} {}", theme.l("Attack"), theme.positive("+1,500"), theme.p("+2")),
+            format!("{} {} {}", theme.l("Zulrah"), theme.positive("+3"), theme.p("new")),
+        ];
+        assert_eq!(line, theme.join(&expected));
+
+        let line = newer.diff(&older).line(&theme, &[]);
+        assert!(line.contains(&theme.l("Activity 1")), "{:?}", line);
+
+        let plain = Theme {
+            plain: true,
+            ..theme
+        };
+        assert_eq!(
+            newer.diff(&older).line(&plain, &["Zulrah"]),
+            "[Overall] +1,500 (+2) | [Attack] +1,500 (+2) | [Zulrah] +3 (new)"
+        );
+    }
+}
@@ -1,7 +1,20 @@
+pub mod abi;
 pub mod author;
 pub mod database;
+pub mod ffi;
+pub mod hiscores;
+pub mod locale;
+pub mod number;
+pub mod plugin;
+pub mod preset;
+pub mod render;
+pub mod response;
 pub mod snapshot;
 pub mod source;
+pub mod split;
+pub mod style;
+pub mod theme;
+pub mod time;
 
 use format_num::NumberFormat;
 #[allow(unused_imports)]
@@ -11,24 +24,29 @@ use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
 use std::sync::LazyLock;
 
-#[repr(C)]
-pub struct PluginContext {
-    pub cmd: *const c_char,
-    pub param: *const c_char,
-    pub author: *const c_char,
-    pub color: extern "C" fn(*const c_char, *const c_char) -> ColorResult,
-}
-
+pub use abi::PluginContext;
+pub use number::{
+    commas_decimal, commas_int, parse_quantity, short_number, trim_decimal_zeroes, try_commas_from_string,
+};
+
+/// Colors returned by the host's `color` callback.
+///
+/// The strings belong to whoever allocated them. A plugin must hand a
+/// `ColorResult` back through `PluginContext::free_color` (see
+/// `ffi::ColorResultGuard`) rather than freeing it itself, since the host
+/// may use a different allocator or a different build of this crate.
 #[repr(C)]
 pub struct ColorResult {
     pub c1: *const c_char,
     pub c2: *const c_char,
 }
 
+// Always the real codes, even in plain mode: plain mode travels in
+// `PluginContext::plain`, which plugins on older ABIs never read.
 impl From<&Colors> for ColorResult {
     fn from(colors: &Colors) -> Self {
-        let c1 = CString::new(colors.c1.to_string()).unwrap().into_raw();
-        let c2 = CString::new(colors.c2.to_string()).unwrap().into_raw();
+        let c1 = CString::new(colors.code1()).unwrap().into_raw();
+        let c2 = CString::new(colors.code2()).unwrap().into_raw();
 
         ColorResult { c1, c2 }
     }
@@ -51,32 +69,137 @@ pub fn capitalize(s: &str) -> String {
     }
 }
 
-#[derive(Clone, Debug)]
+/// One of the 99 mIRC palette colors, 00 through 98.
+///
+/// Always displayed as two digits, so a color followed by text that starts
+/// with a digit can't be misread (`\x034` + `2` would be color 42).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IrcColor(u8);
+
+impl IrcColor {
+    pub const MAX: u8 = 98;
+
+    pub fn new(n: u8) -> anyhow::Result<Self> {
+        if n > Self::MAX {
+            anyhow::bail!("color {} is out of range, use 0-{}", n, Self::MAX);
+        }
+        Ok(Self(n))
+    }
+
+    pub fn value(self) -> u8 {
+        self.0
+    }
+}
+
+impl std::str::FromStr for IrcColor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        if s.is_empty() || s.len() > 2 || !s.bytes().all(|b| b.is_ascii_digit()) {
+            anyhow::bail!("invalid color {:?}, use a number from 0 to {}", s, Self::MAX);
+        }
+        Self::new(s.parse()?)
+    }
+}
+
+impl std::fmt::Display for IrcColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}", self.0)
+    }
+}
+
+impl From<IrcColor> for style::Color {
+    fn from(color: IrcColor) -> Self {
+        style::Color::Irc(color.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Colors {
-    pub c1: String,
-    pub c2: String,
+    pub c1: IrcColor,
+    pub c2: IrcColor,
+    /// Background shared by both colors, if the user picked one.
+    pub bg: Option<IrcColor>,
+    /// The user asked for output without any control codes, e.g. for a
+    /// screen reader. The colors are kept so they come back when it's off.
+    pub plain: bool,
 }
 
 impl Colors {
-    pub fn color1() -> String {
-        "14".to_string()
+    pub fn new(c1: IrcColor, c2: IrcColor) -> Self {
+        Self {
+            c1,
+            c2,
+            bg: None,
+            plain: false,
+        }
+    }
+
+    /// Parse color codes as stored or passed over FFI, where either code may
+    /// carry the background as `fg,bg`. Rejects anything that isn't 0-98.
+    pub fn from_codes(c1: &str, c2: &str) -> anyhow::Result<Self> {
+        let (c1, bg1) = c1.split_once(',').map_or((c1, None), |(fg, bg)| (fg, Some(bg)));
+        let (c2, bg2) = c2.split_once(',').map_or((c2, None), |(fg, bg)| (fg, Some(bg)));
+
+        Ok(Self {
+            c1: c1.parse()?,
+            c2: c2.parse()?,
+            bg: bg1.or(bg2).map(str::parse).transpose()?,
+            plain: false,
+        })
     }
 
-    pub fn color2() -> String {
-        "04".to_string()
+    pub fn with_plain(mut self, plain: bool) -> Self {
+        self.plain = plain;
+        self
+    }
+
+    pub fn with_background(mut self, bg: IrcColor) -> Self {
+        self.bg = Some(bg);
+        self
+    }
+
+    /// The code to put after `\x03` for `c1`, including the background.
+    pub fn code1(&self) -> String {
+        Self::code(self.c1, self.bg)
+    }
+
+    /// The code to put after `\x03` for `c2`, including the background.
+    pub fn code2(&self) -> String {
+        Self::code(self.c2, self.bg)
+    }
+
+    fn code(fg: IrcColor, bg: Option<IrcColor>) -> String {
+        match bg {
+            Some(bg) => format!("{},{}", fg, bg),
+            None => fg.to_string(),
+        }
+    }
+
+    pub fn color1() -> IrcColor {
+        IrcColor(14)
+    }
+
+    pub fn color2() -> IrcColor {
+        IrcColor(4)
     }
 
     pub fn init() {
         author::cache::init();
+
+        if let Err(e) = theme::load() {
+            log::error!("Failed to load themes: {}", e);
+        }
+        if let Err(e) = locale::load() {
+            log::error!("Failed to load locales: {}", e);
+        }
     }
 }
 
 impl Default for Colors {
     fn default() -> Self {
-        Self {
-            c1: Self::color1(),
-            c2: Self::color2(),
-        }
+        Self::new(Self::color1(), Self::color2())
     }
 }
 
@@ -143,24 +266,27 @@ where
     format!("{}{}{}", c1("("), c2(s), c1(")"))
 }
 
-// Adds commas to a number
+// Adds commas to a number (see `commas_int` for exact 64-bit integers)
 pub fn commas(n: f64, f: &str) -> String {
+    if f == "d" && n.fract() == 0.0 && n.abs() < i128::MAX as f64 {
+        return commas_int(n as i128);
+    }
+
     let num = NumberFormat::new();
 
     num.format(&format!(",{}", f), n)
 }
 
-// Adds commas to a string
+// Adds commas to a string (see `try_commas_from_string` to catch bad input)
 pub fn commas_from_string(n: &str, f: &str) -> String {
-    let n = n.parse::<f64>().unwrap_or(0.0);
-
-    commas(n, f)
+    try_commas_from_string(n, f).unwrap_or_else(|_| commas(n.parse::<f64>().unwrap_or(0.0), f))
 }
 
 static TRAILING_ZEROES_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"\.?0+$").unwrap());
 
 // Removes the trailing zeroes from a string (intended to be used on a float->&str that may have commas)
+#[deprecated(note = "also strips zeroes from integers (\"100\" becomes \"1\"); use trim_decimal_zeroes")]
 pub fn remove_trailing_zeroes(str: &str) -> String {
     TRAILING_ZEROES_RE.replace_all(str, "").to_string()
 }
@@ -185,9 +311,15 @@ pub fn convert_split_to_string(split: Vec<&str>) -> Vec<String> {
     split.into_iter().map(|s| s.to_string()).collect()
 }
 
+/// # Safety
+/// `ptr` must be null or point to a valid nul-terminated string.
 #[allow(dead_code)]
-pub fn to_str_or_default(ptr: *const c_char) -> String {
-    let cstr = unsafe { CStr::from_ptr(ptr) };
+pub unsafe fn to_str_or_default(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+
+    let cstr = CStr::from_ptr(ptr);
     cstr.to_str().unwrap_or_default().to_owned()
 }
 
@@ -373,6 +505,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_remove_trailing_zeroes() {
         assert_eq!(remove_trailing_zeroes("0.00000"), "0");
         assert_eq!(remove_trailing_zeroes("1.00000"), "1");
@@ -395,6 +528,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_remove_trailing_zeroes_partial() {
         assert_eq!(remove_trailing_zeroes("1.50"), "1.5");
         assert_eq!(remove_trailing_zeroes("3.14"), "3.14");
@@ -403,6 +537,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_remove_trailing_zeroes_no_decimal() {
         assert_eq!(remove_trailing_zeroes("100"), "1");
         assert_eq!(remove_trailing_zeroes("42"), "42");
@@ -410,6 +545,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_remove_trailing_zeroes_empty() {
         assert_eq!(remove_trailing_zeroes(""), "");
     }
@@ -466,14 +602,66 @@ mod tests {
     #[test]
     fn test_colors_default() {
         let colors = Colors::default();
-        assert_eq!(colors.c1, "14");
-        assert_eq!(colors.c2, "04");
+        assert_eq!(colors.c1, IrcColor::new(14).unwrap());
+        assert_eq!(colors.c2, IrcColor::new(4).unwrap());
+    }
+
+    #[test]
+    fn test_colors_background() {
+        let colors = Colors::default().with_background(IrcColor::new(1).unwrap());
+        assert_eq!(colors.code1(), "14,01");
+        assert_eq!(colors.code2(), "04,01");
+        assert_eq!(Colors::default().code1(), "14");
+
+        let parsed = Colors::from_codes("14,01", "04,01").unwrap();
+        assert_eq!(parsed.c1, IrcColor::new(14).unwrap());
+        assert_eq!(parsed.c2, IrcColor::new(4).unwrap());
+        assert_eq!(parsed.bg, Some(IrcColor::new(1).unwrap()));
+        assert_eq!(Colors::from_codes("03", "07").unwrap().bg, None);
+    }
+
+    #[test]
+    fn test_colors_plain_over_ffi() {
+        // Plain mode keeps the real codes so older plugins never see empty ones.
+        let plain = Colors::from_codes("03", "07").unwrap().with_plain(true);
+        let result = ColorResult::from(&plain);
+        unsafe {
+            assert_eq!(CStr::from_ptr(result.c1).to_str().unwrap(), "03");
+            assert_eq!(CStr::from_ptr(result.c2).to_str().unwrap(), "07");
+        }
+        unsafe { ffi::reinze_free_color_result(result) };
+
+        assert!(Colors::from_codes("", "").is_err());
+        assert!(!Colors::from_codes("03", "07").unwrap().plain);
+    }
+
+    #[test]
+    fn test_irc_color_parse() {
+        assert_eq!("4".parse::<IrcColor>().unwrap(), IrcColor::new(4).unwrap());
+        assert_eq!("04".parse::<IrcColor>().unwrap(), IrcColor::new(4).unwrap());
+        assert_eq!("98".parse::<IrcColor>().unwrap().value(), 98);
+        assert_eq!(" 7 ".parse::<IrcColor>().unwrap(), IrcColor::new(7).unwrap());
+        assert!("red".parse::<IrcColor>().is_err());
+        assert!("999".parse::<IrcColor>().is_err());
+        assert!("99".parse::<IrcColor>().is_err());
+        assert!("-1".parse::<IrcColor>().is_err());
+        assert!("".parse::<IrcColor>().is_err());
+        assert!(IrcColor::new(99).is_err());
+    }
+
+    #[test]
+    fn test_colors_from_codes_normalizes() {
+        let colors = Colors::from_codes("4", "3,1").unwrap();
+        assert_eq!(colors.code1(), "04,01");
+        assert_eq!(colors.code2(), "03,01");
+        assert!(Colors::from_codes("red", "04").is_err());
+        assert!(Colors::from_codes("14", "04,999").is_err());
     }
 
     #[test]
     fn test_colors_static_methods() {
-        assert_eq!(Colors::color1(), "14");
-        assert_eq!(Colors::color2(), "04");
+        assert_eq!(Colors::color1(), IrcColor::new(14).unwrap());
+        assert_eq!(Colors::color2(), IrcColor::new(4).unwrap());
     }
 
     #[test]
@@ -499,6 +687,15 @@ mod tests {
         assert_eq!(commas_from_string("-1000", "d"), "-1,000");
     }
 
+    #[test]
+    fn test_to_str_or_default() {
+        let s = CString::new("hello").unwrap();
+        unsafe {
+            assert_eq!(to_str_or_default(s.as_ptr()), "hello");
+            assert_eq!(to_str_or_default(std::ptr::null()), "");
+        }
+    }
+
     #[test]
     fn test_capitalize_unicode() {
         assert_eq!(capitalize("über"), "Über");
@@ -511,6 +708,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_c_functions_accept_non_str() {
         assert_eq!(c1(42), "\x031442");
         assert_eq!(c2(3.14), "\x03043.14");
@@ -0,0 +1,368 @@
+use crate::number::{self, ShortNumber};
+use crate::theme::Scope;
+use crate::{commas, database};
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use mysql::params;
+use mysql::prelude::Queryable;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::c_char;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+
+/// How the integer part of a number is split into groups.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grouping {
+    /// Groups of three: 1,234,567.
+    Thousands,
+    /// Three, then groups of two: 12,34,567.
+    Indian,
+}
+
+/// Separators and grouping for writing numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Locale {
+    pub name: &'static str,
+    pub thousands: char,
+    pub decimal: char,
+    pub grouping: Grouping,
+}
+
+pub const EN: Locale = Locale {
+    name: "en",
+    thousands: ',',
+    decimal: '.',
+    grouping: Grouping::Thousands,
+};
+
+pub const LOCALES: [Locale; 6] = [
+    EN,
+    Locale {
+        name: "de",
+        thousands: '.',
+        decimal: ',',
+        grouping: Grouping::Thousands,
+    },
+    Locale {
+        name: "pt",
+        thousands: '.',
+        decimal: ',',
+        grouping: Grouping::Thousands,
+    },
+    Locale {
+        name: "fr",
+        thousands: '\u{202F}',
+        decimal: ',',
+        grouping: Grouping::Thousands,
+    },
+    Locale {
+        name: "de-CH",
+        thousands: '\'',
+        decimal: '.',
+        grouping: Grouping::Thousands,
+    },
+    Locale {
+        name: "en-IN",
+        thousands: ',',
+        decimal: '.',
+        grouping: Grouping::Indian,
+    },
+];
+
+// A number as this crate formats it: "1,234,567.89", "42", "1.5" (in "1.5m").
+static NUMBER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d{1,3}(?:,\d{3})+|\d+)(?:\.(\d+))?").unwrap());
+
+impl Locale {
+    /// Rewrite the numbers in `s`, as formatted by `commas` or
+    /// `short_number`, for this locale. Localize before adding colors, since
+    /// color codes are digits too.
+    pub fn localize(&self, s: &str) -> String {
+        if *self == EN {
+            return s.to_string();
+        }
+
+        NUMBER_RE
+            .replace_all(s, |caps: &Captures| {
+                let int: String = caps[1].chars().filter(char::is_ascii_digit).collect();
+                let int = if caps[1].contains(',') { self.group(&int) } else { int };
+                match caps.get(2) {
+                    Some(fraction) => format!("{}{}{}", int, self.decimal, fraction.as_str()),
+                    None => int,
+                }
+            })
+            .to_string()
+    }
+
+    pub fn commas(&self, n: f64, f: &str) -> String {
+        self.localize(&commas(n, f))
+    }
+
+    pub fn commas_int<T>(&self, n: T) -> String
+    where
+        T: Into<i128>,
+    {
+        self.localize(&number::commas_int(n))
+    }
+
+    pub fn short_number(&self, n: f64, precision: usize) -> String {
+        ShortNumber::new(precision).locale(*self).format(n)
+    }
+
+    pub fn trim_decimal_zeroes(&self, s: &str) -> String {
+        number::trim_decimal_zeroes_with(s, self.decimal)
+    }
+
+    fn group(&self, digits: &str) -> String {
+        let split = |digits: &str, size: usize| -> Vec<String> {
+            let bytes = digits.as_bytes();
+            let first = bytes.len() % size;
+            let mut groups = Vec::new();
+            if first > 0 {
+                groups.push(digits[..first].to_string());
+            }
+            groups.extend(bytes[first..].chunks(size).map(|c| String::from_utf8_lossy(c).into_owned()));
+            groups
+        };
+
+        let groups = match self.grouping {
+            Grouping::Thousands => split(digits, 3),
+            Grouping::Indian if digits.len() > 3 => {
+                let (head, tail) = digits.split_at(digits.len() - 3);
+                let mut groups = split(head, 2);
+                groups.push(tail.to_string());
+                groups
+            }
+            Grouping::Indian => vec![digits.to_string()],
+        };
+
+        groups.join(&self.thousands.to_string())
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        EN
+    }
+}
+
+/// Look up a locale by name, ignoring case. Regional names fall back to
+/// their language, so "pt-BR" is "pt" and "de-AT" is "de".
+impl FromStr for Locale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.trim().replace('_', "-");
+        let find = |name: &str| LOCALES.iter().find(|l| l.name.eq_ignore_ascii_case(name)).copied();
+
+        find(&name)
+            .or_else(|| name.split('-').next().and_then(find))
+            .with_context(|| {
+                let names: Vec<&str> = LOCALES.iter().map(|l| l.name).collect();
+                format!("unknown locale {:?}, try one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+static SETTINGS: LazyLock<ArcSwap<HashMap<String, Locale>>> =
+    LazyLock::new(|| ArcSwap::from_pointee(HashMap::new()));
+
+/// Resolve the locale for a user, falling back from their host to the
+/// channel, the network, the global setting and finally `EN`.
+pub fn resolve(host: &str, network: &str, channel: &str) -> Locale {
+    let settings = SETTINGS.load();
+    let lookup = |scope: Scope| settings.get(&scope.key()).copied();
+
+    let mut scopes = vec![Scope::Host(host.to_string())];
+    if !network.is_empty() {
+        if !channel.is_empty() {
+            scopes.push(Scope::Channel {
+                network: network.to_string(),
+                channel: channel.to_string(),
+            });
+        }
+        scopes.push(Scope::Network(network.to_string()));
+    }
+    scopes.push(Scope::Global);
+
+    scopes.into_iter().find_map(lookup).unwrap_or_default()
+}
+
+/// The locale stored for a scope, from the in-memory cache.
+pub fn get(scope: &Scope) -> Option<Locale> {
+    SETTINGS.load().get(&scope.key()).copied()
+}
+
+/// Set the locale for a scope in memory only; `None` clears it.
+pub fn upsert(scope: &Scope, locale: Option<Locale>) {
+    let key = scope.key();
+
+    SETTINGS.rcu(|current| {
+        let mut new_map = (**current).clone();
+        match locale {
+            Some(locale) => new_map.insert(key.clone(), locale),
+            None => new_map.remove(&key),
+        };
+        Arc::new(new_map)
+    });
+}
+
+/// Set the locale for a scope and persist it; `None` clears it. The
+/// in-memory part only reaches the calling library, so plugins use
+/// `Source::set_locale`, which goes through the host.
+pub fn set(scope: &Scope, locale: Option<Locale>) -> Result<()> {
+    upsert(scope, locale);
+
+    let mut conn = database::connect()
+        .map_err(|e| anyhow::anyhow!("database connection failed: {}", e))?;
+
+    match locale {
+        Some(locale) => conn.exec_drop(
+            "INSERT INTO locales (scope, locale) VALUES (:scope, :locale) ON DUPLICATE KEY UPDATE locale = :locale",
+            params! { "scope" => scope.key(), "locale" => locale.name },
+        ),
+        None => conn.exec_drop(
+            "DELETE FROM locales WHERE scope = :scope",
+            params! { "scope" => scope.key() },
+        ),
+    }
+    .context("failed to save locale")?;
+
+    Ok(())
+}
+
+/// The `set_locale` callback handed to plugins: stores the locale named
+/// `locale` (empty to clear) for the scope whose key is `scope`.
+///
+/// # Safety
+/// `scope` and `locale` must point to valid nul-terminated strings.
+pub unsafe extern "C" fn locale_ffi(scope: *const c_char, locale: *const c_char) -> bool {
+    let scope = CStr::from_ptr(scope).to_str().unwrap_or_default();
+    let locale = CStr::from_ptr(locale).to_str().unwrap_or_default();
+
+    let result = scope
+        .parse::<Scope>()
+        .and_then(|scope| match locale {
+            "" => Ok((scope, None)),
+            name => Ok((scope, Some(name.parse::<Locale>()?))),
+        })
+        .and_then(|(scope, locale)| set(&scope, locale));
+
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("locale_ffi: {}", e);
+            false
+        }
+    }
+}
+
+/// Load every stored locale into memory. `Colors::init` calls this at
+/// startup; names that are no longer known are logged and skipped.
+pub fn load() -> Result<()> {
+    let mut conn = database::connect()
+        .map_err(|e| anyhow::anyhow!("database connection failed: {}", e))?;
+
+    let rows: Vec<(String, String)> = conn
+        .query("SELECT scope, locale FROM locales")
+        .context("failed to query locales")?;
+
+    let mut map = HashMap::new();
+    for (scope, locale) in rows {
+        match locale.parse::<Locale>() {
+            Ok(locale) => {
+                map.insert(scope, locale);
+            }
+            Err(e) => log::error!("Invalid locale for {}: {}", scope, e),
+        }
+    }
+
+    SETTINGS.store(Arc::new(map));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locale(name: &str) -> Locale {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn test_commas() {
+        assert_eq!(locale("de").commas(1_234_567.89, ".2f"), "1.234.567,89");
+        assert_eq!(locale("pt").commas(1_234_567.0, "d"), "1.234.567");
+        assert_eq!(locale("fr").commas(1_234.5, ".1f"), "1\u{202F}234,5");
+        assert_eq!(locale("de-CH").commas_int(-1_234_567), "-1'234'567");
+        assert_eq!(locale("en").commas(1_234_567.89, ".2f"), "1,234,567.89");
+    }
+
+    #[test]
+    fn test_indian_grouping() {
+        let locale = locale("en-IN");
+        assert_eq!(locale.commas_int(1_234_567), "12,34,567");
+        assert_eq!(locale.commas_int(123_456_789_012u64), "1,23,45,67,89,012");
+        assert_eq!(locale.commas_int(1_000), "1,000");
+        assert_eq!(locale.commas_int(999), "999");
+    }
+
+    #[test]
+    fn test_short_number() {
+        assert_eq!(locale("de").short_number(1_500_000.0, 2), "1,5m");
+        assert_eq!(locale("de").short_number(-1_234.0, 1), "-1,2k");
+        assert_eq!(locale("de").short_number(999.5, 1), "999,5");
+        assert_eq!(locale("en").short_number(1_500_000.0, 2), "1.5m");
+    }
+
+    #[test]
+    fn test_localize_leaves_plain_integers() {
+        let de = locale("de");
+        assert_eq!(de.localize("2024"), "2024");
+        assert_eq!(de.localize("1,000 to 2,500.5"), "1.000 to 2.500,5");
+        assert_eq!(de.trim_decimal_zeroes("1.234,50"), "1.234,5");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(locale("DE"), locale("de"));
+        assert_eq!(locale("pt-BR").name, "pt");
+        assert_eq!(locale("de_ch").name, "de-CH");
+        assert_eq!(locale("en-US"), EN);
+        let err = "xx".parse::<Locale>().unwrap_err().to_string();
+        assert!(err.contains("en-IN"), "{}", err);
+        assert_eq!(locale("de-CH").to_string(), "de-CH");
+    }
+
+    #[test]
+    fn test_resolve_hierarchy() {
+        let network = "localetest-net";
+        upsert(&Scope::Network(network.into()), Some(locale("de")));
+        upsert(
+            &Scope::Channel {
+                network: network.into(),
+                channel: "#brasil".into(),
+            },
+            Some(locale("pt")),
+        );
+        upsert(&Scope::Host("swiss@host".into()), Some(locale("de-CH")));
+
+        assert_eq!(resolve("other@host", network, "#lobby").name, "de");
+        assert_eq!(resolve("other@host", network, "#BRASIL").name, "pt");
+        assert_eq!(resolve("swiss@host", network, "#brasil").name, "de-CH");
+        assert_eq!(resolve("other@host", "elsewhere", "#brasil"), EN);
+
+        upsert(&Scope::Host("swiss@host".into()), None);
+        assert_eq!(get(&Scope::Host("swiss@host".into())), None);
+        assert_eq!(resolve("swiss@host", network, "#brasil").name, "pt");
+    }
+}
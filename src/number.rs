@@ -0,0 +1,449 @@
+use crate::commas;
+use crate::locale::Locale;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use std::sync::LazyLock;
+
+const SUFFIXES: [(f64, &str); 4] = [(1e12, "t"), (1e9, "b"), (1e6, "m"), (1e3, "k")];
+
+/// How `short_number` abbreviates: how many decimals to keep, from what size
+/// to start abbreviating, whether suffixes are upper case and which locale
+/// to write the number in.
+///
+/// ```ignore
+/// // In-game stack style: 99,999 stays as is, then 100K, then 10M.
+/// ShortNumber::new(0).threshold(100_000.0).uppercase(true).format(n)
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShortNumber {
+    pub precision: usize,
+    pub threshold: f64,
+    pub uppercase: bool,
+    pub locale: Locale,
+}
+
+impl ShortNumber {
+    pub fn new(precision: usize) -> Self {
+        Self {
+            precision,
+            threshold: 1e3,
+            uppercase: false,
+            locale: Locale::default(),
+        }
+    }
+
+    /// Numbers smaller than this (by absolute value) are written out in full.
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn uppercase(mut self, uppercase: bool) -> Self {
+        self.uppercase = uppercase;
+        self
+    }
+
+    pub fn locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    pub fn format(&self, n: f64) -> String {
+        let abs = n.abs();
+
+        let body = if !abs.is_finite() || abs < self.threshold || abs < 1e3 {
+            trim_decimal_zeroes(&commas(abs, &format!(".{}f", self.precision)))
+        } else {
+            self.abbreviate(abs)
+        };
+        let body = self.locale.localize(&body);
+
+        if n.is_sign_negative() && body.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            format!("-{}", body)
+        } else {
+            body
+        }
+    }
+
+    fn abbreviate(&self, abs: f64) -> String {
+        let start = SUFFIXES.iter().position(|&(size, _)| abs >= size).unwrap_or(SUFFIXES.len() - 1);
+
+        // Rounding can carry into the next unit: 999,950 is 1m, not 1000k.
+        let mut i = start;
+        loop {
+            let (size, suffix) = SUFFIXES[i];
+            let scaled = format!("{:.*}", self.precision, abs / size);
+            if i == 0 || scaled.parse::<f64>().unwrap_or(0.0) < 1e3 {
+                let suffix = if self.uppercase {
+                    suffix.to_uppercase()
+                } else {
+                    suffix.to_string()
+                };
+                return format!("{}{}", trim_decimal_zeroes(&scaled), suffix);
+            }
+            i -= 1;
+        }
+    }
+}
+
+/// Abbreviate `n` with a k/m/b/t suffix, keeping at most `precision`
+/// decimals: `short_number(1_234_567_890.0, 2)` is `1.23b`. Numbers under
+/// a thousand are written out in full.
+pub fn short_number(n: f64, precision: usize) -> String {
+    ShortNumber::new(precision).format(n)
+}
+
+/// Drop zeroes after the decimal point, and the point itself if nothing is
+/// left: "1,000.50" is "1,000.5" and "2.00m" is "2m". Unlike
+/// `remove_trailing_zeroes`, integers such as "100" are left alone.
+pub fn trim_decimal_zeroes(s: &str) -> String {
+    trim_decimal_zeroes_with(s, '.')
+}
+
+/// `trim_decimal_zeroes` for a locale with a different decimal separator,
+/// e.g. `trim_decimal_zeroes_with("1.234,50", ',')` is "1.234,5".
+pub fn trim_decimal_zeroes_with(s: &str, decimal: char) -> String {
+    let Some(point) = s.rfind(decimal) else {
+        return s.to_string();
+    };
+    let int = &s[..point];
+    let rest = &s[point + decimal.len_utf8()..];
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (fraction, suffix) = rest.split_at(digits);
+
+    // The point has to sit between digits with at most a unit after the
+    // fraction. Anything else, like a second number, is left untouched.
+    if !int.ends_with(|c: char| c.is_ascii_digit())
+        || fraction.is_empty()
+        || suffix.contains(|c: char| c.is_ascii_digit())
+    {
+        return s.to_string();
+    }
+
+    match fraction.trim_end_matches('0') {
+        "" => format!("{}{}", int, suffix),
+        fraction => format!("{}{}{}{}", int, decimal, fraction, suffix),
+    }
+}
+
+static QUANTITY_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^([+-])?(\d+(?:[,_]\d+)*)?(\.\d+)?(?:e([+-]?\d+))?\s*([kmbt])?$").unwrap()
+});
+
+static GROUPED_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\d{1,3}(?:,\d{3})+$").unwrap());
+
+/// Parse an amount the way users type it: "10,000", "1.5m", "250K", "2b",
+/// "1_000_000", "1e6". The inverse of `commas` and `short_number`.
+///
+/// Commas must group thousands, so "1,5" is rejected rather than guessed at,
+/// as is mixing a suffix with an exponent ("1e3k").
+pub fn parse_quantity(s: &str) -> Result<f64> {
+    let caps = QUANTITY_RE
+        .captures(s.trim())
+        .with_context(|| format!("invalid quantity {:?}, use e.g. 10,000, 1.5m, 250k or 1e6", s))?;
+
+    let int = caps.get(2).map_or("", |m| m.as_str());
+    let fraction = caps.get(3).map_or("", |m| m.as_str());
+    let exponent = caps.get(4).map(|m| m.as_str());
+    let suffix = caps.get(5).map(|m| m.as_str().to_ascii_lowercase());
+
+    if int.is_empty() && fraction.is_empty() {
+        bail!("invalid quantity {:?}, no number given", s);
+    }
+    if exponent.is_some() && suffix.is_some() {
+        bail!("ambiguous quantity {:?}, use either a suffix or an exponent", s);
+    }
+    if int.contains(',') && int.contains('_') {
+        bail!("ambiguous quantity {:?}, use either commas or underscores", s);
+    }
+    if int.contains(',') && !GROUPED_RE.is_match(int) {
+        bail!("ambiguous quantity {:?}, commas separate thousands; use a period for decimals", s);
+    }
+
+    let mut number: String = int.chars().filter(char::is_ascii_digit).collect();
+    if number.is_empty() {
+        number.push('0');
+    }
+    number.push_str(fraction);
+    if let Some(exponent) = exponent {
+        number.push_str(&format!("e{}", exponent));
+    }
+    let mut n: f64 = number.parse().with_context(|| format!("invalid quantity {:?}", s))?;
+
+    if let Some(suffix) = suffix {
+        let (size, _) = SUFFIXES.iter().find(|(_, name)| *name == suffix).unwrap();
+        n *= size;
+    }
+    if !n.is_finite() {
+        bail!("quantity {:?} is too large", s);
+    }
+
+    Ok(if caps.get(1).map(|m| m.as_str()) == Some("-") { -n } else { n })
+}
+
+static DECIMAL_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^([+-])?(\d+)(?:\.(\d+))?$").unwrap());
+
+/// Group the digits of an integer with commas. Exact for every `i64`,
+/// `u64` and `i128`, unlike going through `commas` and `f64`.
+pub fn commas_int<T>(n: T) -> String
+where
+    T: Into<i128>,
+{
+    let n = n.into();
+    let digits = group(&n.unsigned_abs().to_string());
+    if n < 0 {
+        format!("-{}", digits)
+    } else {
+        digits
+    }
+}
+
+/// Group the integer part of a decimal string with commas, keeping every
+/// digit as written: "1234567.890" is "1,234,567.890".
+pub fn commas_decimal(s: &str) -> Result<String> {
+    let (negative, int, fraction) = split_decimal(s)?;
+    Ok(join_decimal(negative, &int, &fraction))
+}
+
+/// Like `commas_from_string`, but exact and fallible: the input must be a
+/// plain decimal number, and `f` either "d" or a fixed precision such as
+/// ".2f". Rounds half away from zero on the digits themselves.
+pub fn try_commas_from_string(n: &str, f: &str) -> Result<String> {
+    let (negative, int, fraction) = split_decimal(n)?;
+    let places = match f {
+        "d" => 0,
+        f => f
+            .strip_prefix('.')
+            .and_then(|f| f.strip_suffix('f'))
+            .and_then(|places| places.parse().ok())
+            .with_context(|| format!("unsupported format {:?}, use \"d\" or e.g. \".2f\"", f))?,
+    };
+
+    let (int, fraction) = round_digits(&int, &fraction, places);
+    Ok(join_decimal(negative, &int, &fraction))
+}
+
+fn split_decimal(s: &str) -> Result<(bool, String, String)> {
+    let caps = DECIMAL_RE
+        .captures(s.trim())
+        .with_context(|| format!("invalid number {:?}", s))?;
+
+    let negative = caps.get(1).map(|m| m.as_str()) == Some("-");
+    let int = caps[2].trim_start_matches('0');
+    let int = if int.is_empty() { "0" } else { int };
+    let fraction = caps.get(3).map_or("", |m| m.as_str());
+
+    Ok((negative, int.to_string(), fraction.to_string()))
+}
+
+fn join_decimal(negative: bool, int: &str, fraction: &str) -> String {
+    let zero = int.chars().chain(fraction.chars()).all(|c| c == '0');
+    let sign = if negative && !zero { "-" } else { "" };
+    if fraction.is_empty() {
+        format!("{}{}", sign, group(int))
+    } else {
+        format!("{}{}.{}", sign, group(int), fraction)
+    }
+}
+
+// Rounds `int.fraction` to `places` decimals, padding with zeroes if
+// there are fewer.
+fn round_digits(int: &str, fraction: &str, places: usize) -> (String, String) {
+    if fraction.len() <= places {
+        return (int.to_string(), format!("{:0<width$}", fraction, width = places));
+    }
+
+    let mut digits: Vec<u8> = format!("{}{}", int, &fraction[..places]).into_bytes();
+    if fraction.as_bytes()[places] >= b'5' {
+        let mut i = digits.len();
+        loop {
+            if i == 0 {
+                digits.insert(0, b'1');
+                break;
+            }
+            i -= 1;
+            if digits[i] == b'9' {
+                digits[i] = b'0';
+            } else {
+                digits[i] += 1;
+                break;
+            }
+        }
+    }
+
+    let digits = String::from_utf8(digits).unwrap();
+    let (int, fraction) = digits.split_at(digits.len() - places);
+    (int.to_string(), fraction.to_string())
+}
+
+// Inserts a comma every three digits from the right.
+fn group(digits: &str) -> String {
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_number() {
+        assert_eq!(short_number(1_234_567_890.0, 2), "1.23b");
+        assert_eq!(short_number(450_500.0, 1), "450.5k");
+        assert_eq!(short_number(12_000_000.0, 2), "12m");
+        assert_eq!(short_number(2_500_000_000_000.0, 1), "2.5t");
+        assert_eq!(short_number(1_000.0, 0), "1k");
+    }
+
+    #[test]
+    fn test_small_numbers_in_full() {
+        assert_eq!(short_number(999.0, 2), "999");
+        assert_eq!(short_number(12.345, 1), "12.3");
+        assert_eq!(short_number(0.0, 2), "0");
+    }
+
+    #[test]
+    fn test_rounds_into_next_unit() {
+        assert_eq!(short_number(999_950.0, 1), "1m");
+        assert_eq!(short_number(999_949.0, 1), "999.9k");
+        assert_eq!(short_number(999_999_999.0, 2), "1b");
+        assert_eq!(short_number(999_500.0, 0), "1m");
+    }
+
+    #[test]
+    fn test_largest_unit_does_not_carry() {
+        assert_eq!(short_number(1_500_000_000_000_000.0, 0), "1500t");
+    }
+
+    #[test]
+    fn test_negative() {
+        assert_eq!(short_number(-1_234_567.0, 2), "-1.23m");
+        assert_eq!(short_number(-999_950.0, 1), "-1m");
+        assert_eq!(short_number(-42.0, 0), "-42");
+        assert_eq!(short_number(-0.001, 1), "0");
+    }
+
+    #[test]
+    fn test_threshold_and_case() {
+        let stack = ShortNumber::new(0).threshold(100_000.0).uppercase(true);
+        assert_eq!(stack.format(99_999.0), "99,999");
+        assert_eq!(stack.format(100_000.0), "100K");
+        assert_eq!(stack.format(10_000_000.0), "10M");
+        assert_eq!(ShortNumber::new(1).threshold(0.0).format(500.0), "500");
+    }
+
+    #[test]
+    fn test_parse_quantity() {
+        assert_eq!(parse_quantity("10,000").unwrap(), 10_000.0);
+        assert_eq!(parse_quantity("1.5m").unwrap(), 1_500_000.0);
+        assert_eq!(parse_quantity("250K").unwrap(), 250_000.0);
+        assert_eq!(parse_quantity("2b").unwrap(), 2e9);
+        assert_eq!(parse_quantity("3T").unwrap(), 3e12);
+        assert_eq!(parse_quantity("1_000_000").unwrap(), 1e6);
+        assert_eq!(parse_quantity("1e6").unwrap(), 1e6);
+        assert_eq!(parse_quantity("2.5E-1").unwrap(), 0.25);
+        assert_eq!(parse_quantity(" 42 ").unwrap(), 42.0);
+        assert_eq!(parse_quantity(".5k").unwrap(), 500.0);
+        assert_eq!(parse_quantity("1,234.5 m").unwrap(), 1_234_500_000.0);
+        assert_eq!(parse_quantity("-1.5k").unwrap(), -1_500.0);
+        assert_eq!(parse_quantity("+7").unwrap(), 7.0);
+    }
+
+    #[test]
+    fn test_parse_quantity_round_trip() {
+        for n in [450_500.0, 1_230_000_000.0, 999_000.0] {
+            assert_eq!(parse_quantity(&short_number(n, 2)).unwrap(), n);
+            assert_eq!(parse_quantity(&commas(n, "d")).unwrap(), n);
+        }
+    }
+
+    #[test]
+    fn test_parse_quantity_rejects() {
+        for bad in [
+            "", "k", "-", "abc", "1.5.3", "1,5", "1,0000", "12,34,567", "1,000_000", "1e3k", "1__0", "_1", "1x",
+            "1e999", "1 000",
+        ] {
+            assert!(parse_quantity(bad).is_err(), "{:?}", bad);
+        }
+        let err = parse_quantity("1,5").unwrap_err().to_string();
+        assert!(err.contains("commas separate thousands"), "{}", err);
+    }
+
+    #[test]
+    fn test_commas_int() {
+        assert_eq!(commas_int(0), "0");
+        assert_eq!(commas_int(999), "999");
+        assert_eq!(commas_int(1_000), "1,000");
+        assert_eq!(commas_int(-1_234_567i64), "-1,234,567");
+        assert_eq!(commas_int(u64::MAX), "18,446,744,073,709,551,615");
+        assert_eq!(commas_int(i64::MIN), "-9,223,372,036,854,775,808");
+        assert_eq!(commas_int(i128::MIN), "-170,141,183,460,469,231,731,687,303,715,884,105,728");
+        // 2^53 + 1 has no f64 representation.
+        assert_eq!(commas_int(9_007_199_254_740_993u64), "9,007,199,254,740,993");
+    }
+
+    #[test]
+    fn test_commas_decimal() {
+        assert_eq!(commas_decimal("1234567.890").unwrap(), "1,234,567.890");
+        assert_eq!(commas_decimal("-0012").unwrap(), "-12");
+        assert_eq!(commas_decimal("-0.000").unwrap(), "0.000");
+        assert_eq!(
+            commas_decimal("123456789012345678901234567890.123456789").unwrap(),
+            "123,456,789,012,345,678,901,234,567,890.123456789"
+        );
+        assert!(commas_decimal("1,000").is_err());
+    }
+
+    #[test]
+    fn test_try_commas_from_string() {
+        assert_eq!(try_commas_from_string("9007199254740993", "d").unwrap(), "9,007,199,254,740,993");
+        assert_eq!(try_commas_from_string(" 1234.5 ", "d").unwrap(), "1,235");
+        assert_eq!(try_commas_from_string("1234.5", ".2f").unwrap(), "1,234.50");
+        assert_eq!(try_commas_from_string("999.995", ".2f").unwrap(), "1,000.00");
+        assert_eq!(try_commas_from_string("-99.5", "d").unwrap(), "-100");
+        assert_eq!(try_commas_from_string("-0.4", "d").unwrap(), "0");
+        assert_eq!(try_commas_from_string("+42", "d").unwrap(), "42");
+    }
+
+    #[test]
+    fn test_try_commas_from_string_errors() {
+        for bad in ["", "abc", "1e5", "1.", ".5", "12a", "NaN"] {
+            assert!(try_commas_from_string(bad, "d").is_err(), "{:?}", bad);
+        }
+        let err = try_commas_from_string("1", ",.2%").unwrap_err().to_string();
+        assert!(err.contains("unsupported format"), "{}", err);
+    }
+
+    #[test]
+    fn test_trim_decimal_zeroes() {
+        assert_eq!(trim_decimal_zeroes("1,000.50"), "1,000.5");
+        assert_eq!(trim_decimal_zeroes("1,000.00"), "1,000");
+        assert_eq!(trim_decimal_zeroes("0.000"), "0");
+        assert_eq!(trim_decimal_zeroes("2.00m"), "2m");
+        assert_eq!(trim_decimal_zeroes("-3.140"), "-3.14");
+        assert_eq!(trim_decimal_zeroes("3.14"), "3.14");
+    }
+
+    #[test]
+    fn test_trim_decimal_zeroes_leaves_integers() {
+        assert_eq!(trim_decimal_zeroes("100"), "100");
+        assert_eq!(trim_decimal_zeroes("1,000,000"), "1,000,000");
+        assert_eq!(trim_decimal_zeroes("10k"), "10k");
+        assert_eq!(trim_decimal_zeroes(""), "");
+        assert_eq!(trim_decimal_zeroes("v1.0 build 20"), "v1.0 build 20");
+    }
+
+    #[test]
+    fn test_trim_decimal_zeroes_with_locale() {
+        assert_eq!(trim_decimal_zeroes_with("1.234,50", ','), "1.234,5");
+        assert_eq!(trim_decimal_zeroes_with("1.234,00", ','), "1.234");
+        assert_eq!(trim_decimal_zeroes_with("1.000", ','), "1.000");
+        assert_eq!(trim_decimal_zeroes_with("1 000,10", ','), "1 000,1");
+    }
+}
@@ -0,0 +1,258 @@
+use crate::response::{FfiResponse, Response};
+use crate::source::Source;
+use crate::PluginContext;
+use anyhow::Result;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// The signature every plugin handler implements.
+pub type Handler = fn(&Source) -> Result<Vec<String>>;
+
+/// A handler that wants notices, actions or other targets.
+pub type ResponseHandler = fn(&Source) -> Result<Response>;
+
+/// Run `handler` against the context the host passed in and hand the lines
+/// back as a single newline-separated C string.
+///
+/// Nothing unwinds across the FFI boundary: a bad context, an `Err` or a
+/// panic are all logged and turned into an empty string. The returned
+/// pointer must be released with `free`.
+///
+/// # Safety
+/// `ctx` must be null or point to a `PluginContext` whose strings are valid,
+/// NUL-terminated and live for the duration of the call.
+pub unsafe fn call(ctx: *const PluginContext, handler: Handler) -> *mut c_char {
+    let output = guarded(ctx, |source| handler(source).map(|lines| lines.join("\n")));
+
+    into_c_string(output.unwrap_or_default())
+}
+
+/// Like `call`, but for handlers that build a `Response`. The result must
+/// be released with `reinze_free_response`.
+///
+/// # Safety
+/// Same as `call`.
+pub unsafe fn respond(ctx: *const PluginContext, handler: ResponseHandler) -> FfiResponse {
+    guarded(ctx, handler).unwrap_or_default().into()
+}
+
+/// Release a string returned by `call`.
+///
+/// # Safety
+/// `ptr` must be null or a pointer returned by `call` that has not been
+/// freed yet.
+pub unsafe fn free(ptr: *mut c_char) {
+    crate::ffi::reinze_free_string(ptr)
+}
+
+/// Build the `Source` and run `f`, logging and swallowing errors and panics.
+///
+/// # Safety
+/// Same as `call`.
+unsafe fn guarded<T, F>(ctx: *const PluginContext, f: F) -> Option<T>
+where
+    F: FnOnce(&Source) -> Result<T>,
+{
+    catch_unwind(AssertUnwindSafe(|| unsafe { run(ctx, f) })).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        log::error!("plugin handler panicked: {}", message);
+
+        None
+    })
+}
+
+/// # Safety
+/// Same as `call`.
+unsafe fn run<T, F>(ctx: *const PluginContext, f: F) -> Option<T>
+where
+    F: FnOnce(&Source) -> Result<T>,
+{
+    let Some(ctx) = ctx.as_ref() else {
+        log::error!("plugin called with a null context");
+        return None;
+    };
+
+    let source = match Source::from_context(ctx) {
+        Ok(source) => source,
+        Err(e) => {
+            log::error!("{}", e);
+            return None;
+        }
+    };
+
+    match f(&source) {
+        Ok(output) => Some(output),
+        Err(e) => {
+            log::error!("plugin handler failed: {:#}", e);
+            None
+        }
+    }
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    // Strip interior NULs so `CString::new` cannot fail.
+    CString::new(s.replace('\0', "")).unwrap_or_default().into_raw()
+}
+
+/// Export a plain handler as the C entry point the host calls, along with
+/// the function that frees its output.
+///
+/// ```ignore
+/// fn handler(source: &Source) -> anyhow::Result<Vec<String>> {
+///     Ok(vec![source.l("Hello")])
+/// }
+///
+/// reinze_lib_common::reinze_plugin!(handler);
+/// // or, with explicit symbol names:
+/// reinze_lib_common::reinze_plugin!(handler => exported, free_exported);
+///
+/// // Handlers returning a `Response` use the `response` form:
+/// fn stats(source: &Source) -> anyhow::Result<Response> {
+///     Ok(source.reply("line one").notice("just for you"))
+/// }
+///
+/// reinze_lib_common::reinze_plugin!(response stats);
+/// ```
+#[macro_export]
+macro_rules! reinze_plugin {
+    (response $handler:path) => {
+        $crate::reinze_plugin!(response $handler => reinze_plugin_respond, reinze_plugin_free_response);
+    };
+    (response $handler:path => $call:ident, $free:ident) => {
+        #[no_mangle]
+        pub extern "C" fn $call(
+            ctx: *const $crate::PluginContext,
+        ) -> $crate::response::FfiResponse {
+            unsafe { $crate::plugin::respond(ctx, $handler) }
+        }
+
+        #[no_mangle]
+        pub extern "C" fn $free(response: $crate::response::FfiResponse) {
+            unsafe { $crate::response::reinze_free_response(response) }
+        }
+    };
+    ($handler:path) => {
+        $crate::reinze_plugin!($handler => reinze_plugin_call, reinze_plugin_free);
+    };
+    ($handler:path => $call:ident, $free:ident) => {
+        #[no_mangle]
+        pub extern "C" fn $call(
+            ctx: *const $crate::PluginContext,
+        ) -> *mut ::std::os::raw::c_char {
+            unsafe { $crate::plugin::call(ctx, $handler) }
+        }
+
+        #[no_mangle]
+        pub extern "C" fn $free(ptr: *mut ::std::os::raw::c_char) {
+            unsafe { $crate::plugin::free(ptr) }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ColorResult;
+    use std::ffi::CStr;
+
+    extern "C" fn stub_color(_host: *const c_char, _colors: *const c_char) -> ColorResult {
+        ColorResult::default()
+    }
+
+    fn echo(source: &Source) -> Result<Vec<String>> {
+        Ok(vec![source.command.clone(), source.query.clone()])
+    }
+
+    fn fails(_source: &Source) -> Result<Vec<String>> {
+        anyhow::bail!("nope")
+    }
+
+    fn panics(_source: &Source) -> Result<Vec<String>> {
+        panic!("boom")
+    }
+
+    fn multi(source: &Source) -> Result<Response> {
+        Ok(source.reply(&source.command).notice(&source.query))
+    }
+
+    crate::reinze_plugin!(echo => test_plugin_call, test_plugin_free);
+    crate::reinze_plugin!(response multi => test_plugin_respond, test_plugin_free_response);
+
+    fn invoke(handler: Handler) -> String {
+        let cmd = CString::new("stats").unwrap();
+        let param = CString::new("zezima").unwrap();
+        let author = CString::new("nick!ident@host").unwrap();
+        let ctx = PluginContext::new(cmd.as_ptr(), param.as_ptr(), author.as_ptr(), stub_color);
+
+        let ptr = unsafe { call(&ctx, handler) };
+        let out = unsafe { CStr::from_ptr(ptr) }.to_str().unwrap().to_owned();
+        unsafe { free(ptr) };
+
+        out
+    }
+
+    #[test]
+    fn test_call_joins_lines() {
+        assert_eq!(invoke(echo), "stats\nzezima");
+    }
+
+    #[test]
+    fn test_call_error_is_empty() {
+        assert_eq!(invoke(fails), "");
+    }
+
+    #[test]
+    fn test_call_catches_panic() {
+        assert_eq!(invoke(panics), "");
+    }
+
+    #[test]
+    fn test_call_null_context() {
+        let ptr = unsafe { call(std::ptr::null(), echo) };
+        assert_eq!(unsafe { CStr::from_ptr(ptr) }.to_str().unwrap(), "");
+        unsafe { free(ptr) };
+    }
+
+    #[test]
+    fn test_free_null() {
+        unsafe { free(std::ptr::null_mut()) };
+    }
+
+    #[test]
+    fn test_macro_exports() {
+        let cmd = CString::new("ge").unwrap();
+        let param = CString::new("dragon bones").unwrap();
+        let author = CString::new("nick!ident@host").unwrap();
+        let ctx = PluginContext::new(cmd.as_ptr(), param.as_ptr(), author.as_ptr(), stub_color);
+
+        let ptr = test_plugin_call(&ctx);
+        assert_eq!(unsafe { CStr::from_ptr(ptr) }.to_str().unwrap(), "ge\ndragon bones");
+        test_plugin_free(ptr);
+    }
+
+    #[test]
+    fn test_respond_macro_exports() {
+        let cmd = CString::new("stats").unwrap();
+        let param = CString::new("zezima").unwrap();
+        let author = CString::new("nick!ident@host").unwrap();
+        let ctx = PluginContext::new(cmd.as_ptr(), param.as_ptr(), author.as_ptr(), stub_color);
+
+        let ffi = test_plugin_respond(&ctx);
+        let response = unsafe { ffi.to_response() };
+        test_plugin_free_response(ffi);
+
+        assert_eq!(response, Response::new().privmsg("stats").notice("zezima"));
+    }
+
+    #[test]
+    fn test_respond_null_context_is_empty() {
+        let ffi = unsafe { respond(std::ptr::null(), multi) };
+        assert_eq!(ffi.len, 0);
+        unsafe { crate::response::reinze_free_response(ffi) };
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{Colors, IrcColor};
+use anyhow::{Context, Result};
+
+/// A named pair of colors users can pick without knowing mIRC numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    c1: u8,
+    c2: u8,
+}
+
+impl Preset {
+    pub fn colors(&self) -> Colors {
+        Colors::new(IrcColor::new(self.c1).unwrap(), IrcColor::new(self.c2).unwrap())
+    }
+
+    /// The preset's name in its own colors, bracketed like `l`.
+    pub fn render(&self) -> String {
+        let colors = self.colors();
+        format!("\x03{}[\x03{}{}\x03{}]", colors.c1, colors.c2, self.name, colors.c1)
+    }
+}
+
+pub const PRESETS: [Preset; 5] = [
+    Preset {
+        name: "default",
+        description: "gray and red",
+        c1: 14,
+        c2: 4,
+    },
+    Preset {
+        name: "ocean",
+        description: "teal and light blue",
+        c1: 10,
+        c2: 12,
+    },
+    Preset {
+        name: "high-contrast",
+        description: "white and yellow, for dark backgrounds",
+        c1: 0,
+        c2: 8,
+    },
+    Preset {
+        name: "deuteranopia-safe",
+        description: "blue and orange, no red/green pairing",
+        c1: 12,
+        c2: 7,
+    },
+    Preset {
+        name: "tritanopia-safe",
+        description: "teal and red, no blue/yellow pairing",
+        c1: 10,
+        c2: 4,
+    },
+];
+
+/// Look up a preset by name, ignoring case.
+pub fn find(name: &str) -> Result<&'static Preset> {
+    PRESETS
+        .iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+        .with_context(|| format!("unknown preset {:?}, try one of: {}", name, names().join(", ")))
+}
+
+pub fn names() -> Vec<&'static str> {
+    PRESETS.iter().map(|p| p.name).collect()
+}
+
+/// Every preset rendered in its own colors, for a `!colors` listing.
+pub fn listing() -> String {
+    PRESETS.iter().map(Preset::render).collect::<Vec<String>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_matches_colors_default() {
+        assert_eq!(find("default").unwrap().colors(), Colors::default());
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find("Ocean").unwrap().name, "ocean");
+        assert_eq!(find(" high-contrast ").unwrap().colors().c2, IrcColor::new(8).unwrap());
+        let err = find("neon").unwrap_err().to_string();
+        assert!(err.contains("neon"));
+        assert!(err.contains("tritanopia-safe"));
+    }
+
+    #[test]
+    fn test_all_presets_valid() {
+        for preset in PRESETS.iter() {
+            let colors = preset.colors();
+            assert_ne!(colors.c1, colors.c2, "{}", preset.name);
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(find("default").unwrap().render(), crate::l("default"));
+        assert_eq!(find("ocean").unwrap().render(), "\x0310[\x0312ocean\x0310]");
+    }
+
+    #[test]
+    fn test_listing() {
+        let listing = listing();
+        assert!(listing.starts_with(&crate::l("default")));
+        assert_eq!(listing.matches('[').count(), PRESETS.len());
+        assert_eq!(
+            crate::style::strip_formatting(&listing),
+            "[default] [ocean] [high-contrast] [deuteranopia-safe] [tritanopia-safe]"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use crate::style::{parse, Color, Style};
+
+/// xterm-256 equivalents of mIRC colors 0-98, per the modern IRC client
+/// documentation. Color 99 means "default" and has no entry.
+const IRC_TO_XTERM: [u8; 99] = [
+    15, 0, 4, 2, 9, 1, 5, 3, 11, 10, 6, 14, 12, 13, 8, 7, //
+    52, 94, 100, 58, 22, 29, 23, 24, 17, 54, 53, 89, //
+    88, 130, 142, 64, 28, 35, 30, 25, 18, 91, 90, 125, //
+    124, 166, 184, 106, 34, 49, 37, 33, 19, 129, 127, 161, //
+    196, 208, 226, 154, 46, 86, 51, 75, 21, 171, 201, 198, //
+    203, 215, 227, 191, 83, 122, 87, 111, 63, 177, 207, 205, //
+    217, 223, 229, 193, 157, 158, 159, 153, 147, 183, 219, 212, //
+    16, 233, 235, 237, 239, 241, 244, 247, 250, 254, 231,
+];
+
+const SGR_RESET: &str = "\x1b[0m";
+
+/// The xterm-256 color for an mIRC color number, or `None` for 99/default.
+pub fn xterm_color(n: u8) -> Option<u8> {
+    IRC_TO_XTERM.get(n as usize).copied()
+}
+
+/// Convert IRC formatting codes into ANSI escape sequences for a terminal.
+pub fn to_ansi(s: &str) -> String {
+    let mut out = String::new();
+    let mut current = String::new();
+
+    for span in parse(s) {
+        let codes = sgr(&span.style);
+        if codes != current {
+            if !current.is_empty() {
+                out.push_str(SGR_RESET);
+            }
+            out.push_str(&codes);
+            current = codes;
+        }
+        out.push_str(&span.text);
+    }
+
+    if !current.is_empty() {
+        out.push_str(SGR_RESET);
+    }
+
+    out
+}
+
+fn sgr(style: &Style) -> String {
+    let mut codes: Vec<String> = Vec::new();
+
+    if style.bold {
+        codes.push("1".to_string());
+    }
+    if style.italic {
+        codes.push("3".to_string());
+    }
+    if style.underline {
+        codes.push("4".to_string());
+    }
+    if style.reverse {
+        codes.push("7".to_string());
+    }
+    if style.strikethrough {
+        codes.push("9".to_string());
+    }
+    if let Some(fg) = color(style.fg) {
+        codes.push(format!("38;{}", fg));
+    }
+    if let Some(bg) = color(style.bg) {
+        codes.push(format!("48;{}", bg));
+    }
+
+    if codes.is_empty() {
+        String::new()
+    } else {
+        format!("\x1b[{}m", codes.join(";"))
+    }
+}
+
+fn color(color: Option<Color>) -> Option<String> {
+    match color? {
+        Color::Irc(n) => xterm_color(n).map(|x| format!("5;{}", x)),
+        Color::Hex(rgb) => Some(format!("2;{};{};{}", rgb >> 16, (rgb >> 8) & 0xFF, rgb & 0xFF)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::{bold, color as bg_color, hex, underline};
+    use crate::{c1, c2, l};
+
+    #[test]
+    fn test_plain_text_untouched() {
+        assert_eq!(to_ansi("hello"), "hello");
+        assert_eq!(to_ansi(""), "");
+    }
+
+    #[test]
+    fn test_foreground() {
+        assert_eq!(to_ansi(&c2("red")), "\x1b[38;5;9mred\x1b[0m");
+        assert_eq!(to_ansi(&c1("gray")), "\x1b[38;5;8mgray\x1b[0m");
+    }
+
+    #[test]
+    fn test_l() {
+        assert_eq!(
+            to_ansi(&l("Stats")),
+            "\x1b[38;5;8m[\x1b[0m\x1b[38;5;9mStats\x1b[0m\x1b[38;5;8m]\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_bold_underline() {
+        assert_eq!(to_ansi(&format!("a{}b", bold("x"))), "a\x1b[1mx\x1b[0mb");
+        assert_eq!(to_ansi(&underline(c2("u"))), "\x1b[4;38;5;9mu\x1b[0m");
+    }
+
+    #[test]
+    fn test_background_and_hex() {
+        assert_eq!(to_ansi(&bg_color("w", 0, 1)), "\x1b[38;5;15;48;5;0mw\x1b[0m");
+        assert_eq!(to_ansi(&hex("h", 0xFF8800)), "\x1b[38;2;255;136;0mh\x1b[0m");
+    }
+
+    #[test]
+    fn test_default_color() {
+        assert_eq!(xterm_color(98), Some(231));
+        assert_eq!(xterm_color(99), None);
+        assert_eq!(to_ansi("\x0399x"), "x");
+    }
+}
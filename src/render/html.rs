@@ -0,0 +1,124 @@
+use super::rgb;
+use crate::style::{parse, Color, Style};
+
+/// Convert IRC formatting codes into HTML, wrapping each styled run in a
+/// `<span style="...">`. Text is HTML-escaped.
+pub fn to_html(s: &str) -> String {
+    let mut out = String::new();
+
+    for span in parse(s) {
+        let css = css(&span.style);
+        if css.is_empty() {
+            out.push_str(&escape(&span.text));
+        } else {
+            out.push_str(&format!("<span style=\"{}\">{}</span>", css, escape(&span.text)));
+        }
+    }
+
+    out
+}
+
+fn css(style: &Style) -> String {
+    let mut rules: Vec<String> = Vec::new();
+
+    let (fg, bg) = if style.reverse {
+        (style.bg, style.fg)
+    } else {
+        (style.fg, style.bg)
+    };
+
+    if let Some(fg) = color(fg) {
+        rules.push(format!("color:#{:06X}", fg));
+    }
+    if let Some(bg) = color(bg) {
+        rules.push(format!("background-color:#{:06X}", bg));
+    }
+    if style.bold {
+        rules.push("font-weight:bold".to_string());
+    }
+    if style.italic {
+        rules.push("font-style:italic".to_string());
+    }
+    match (style.underline, style.strikethrough) {
+        (true, true) => rules.push("text-decoration:underline line-through".to_string()),
+        (true, false) => rules.push("text-decoration:underline".to_string()),
+        (false, true) => rules.push("text-decoration:line-through".to_string()),
+        (false, false) => {}
+    }
+    if style.monospace {
+        rules.push("font-family:monospace".to_string());
+    }
+
+    rules.join(";")
+}
+
+fn color(color: Option<Color>) -> Option<u32> {
+    match color? {
+        Color::Irc(n) => rgb(n),
+        Color::Hex(rgb) => Some(rgb),
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::{bold, color as irc_color, hex, Style};
+    use crate::{c2, l};
+
+    #[test]
+    fn test_plain_is_escaped() {
+        assert_eq!(to_html("a < b & \"c\""), "a &lt; b &amp; &quot;c&quot;");
+    }
+
+    #[test]
+    fn test_l() {
+        assert_eq!(
+            to_html(&l("<Stats>")),
+            "<span style=\"color:#7F7F7F\">[</span>\
+             <span style=\"color:#FF0000\">&lt;Stats&gt;</span>\
+             <span style=\"color:#7F7F7F\">]</span>"
+        );
+    }
+
+    #[test]
+    fn test_styles() {
+        assert_eq!(to_html(&bold(c2("x"))), "<span style=\"color:#FF0000;font-weight:bold\">x</span>");
+        assert_eq!(
+            to_html(&Style::new().italic().underline().strikethrough().monospace().apply("y")),
+            "<span style=\"font-style:italic;text-decoration:underline line-through;font-family:monospace\">y</span>"
+        );
+    }
+
+    #[test]
+    fn test_background_hex_and_reverse() {
+        assert_eq!(
+            to_html(&irc_color("w", 1, 8)),
+            "<span style=\"color:#000000;background-color:#FFFF00\">w</span>"
+        );
+        assert_eq!(to_html(&hex("h", 0x123456)), "<span style=\"color:#123456\">h</span>");
+        assert_eq!(
+            to_html(&Style::new().fg(1).bg(8).reverse().apply("r")),
+            "<span style=\"color:#FFFF00;background-color:#000000\">r</span>"
+        );
+    }
+
+    #[test]
+    fn test_default_color_dropped() {
+        assert_eq!(to_html("\x0399x"), "x");
+    }
+}
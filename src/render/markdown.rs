@@ -0,0 +1,113 @@
+use crate::style::{parse, Style};
+
+/// Convert IRC formatting codes into Discord-flavored Markdown.
+///
+/// Bold, italic, underline, strikethrough and monospace carry over; colors
+/// have no Markdown equivalent and are dropped. Markdown in the text itself
+/// is escaped so it shows up literally.
+pub fn to_markdown(s: &str) -> String {
+    // Colors are dropped, so runs that differ only by color can be merged.
+    let mut runs: Vec<(Style, String)> = Vec::new();
+    for span in parse(s) {
+        let style = Style {
+            fg: None,
+            bg: None,
+            reverse: false,
+            ..span.style
+        };
+        match runs.last_mut() {
+            Some((last, text)) if *last == style => text.push_str(&span.text),
+            _ => runs.push((style, span.text)),
+        }
+    }
+
+    runs.into_iter().map(|(style, text)| wrap(&style, &text)).collect()
+}
+
+fn wrap(style: &Style, text: &str) -> String {
+    // Markers must hug the text, so keep surrounding whitespace outside them.
+    let trimmed = text.trim();
+    if trimmed.is_empty() || style.is_plain() {
+        return escape(text);
+    }
+    let start = text.len() - text.trim_start().len();
+    let (leading, rest) = text.split_at(start);
+    let trailing = &rest[trimmed.len()..];
+
+    let mut inner = if style.monospace {
+        format!("`{}`", trimmed.replace('`', "'"))
+    } else {
+        escape(trimmed)
+    };
+
+    for (on, marker) in [
+        (style.strikethrough, "~~"),
+        (style.italic, "*"),
+        (style.underline, "__"),
+        (style.bold, "**"),
+    ] {
+        if on {
+            inner = format!("{}{}{}", marker, inner, marker);
+        }
+    }
+
+    format!("{}{}{}", leading, inner, trailing)
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '*' | '_' | '~' | '`' | '|' | '>') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::{bold, italic, monospace, strikethrough, underline};
+    use crate::{c1, c2, l, p};
+
+    #[test]
+    fn test_colors_dropped() {
+        assert_eq!(to_markdown(&l("Stats")), "[Stats]");
+        assert_eq!(to_markdown(&p(c2(42))), "(42)");
+    }
+
+    #[test]
+    fn test_separator_escaped() {
+        let line = format!("{}{}{}", l("a"), c1(" | "), l("b"));
+        assert_eq!(to_markdown(&line), "[a] \\| [b]");
+    }
+
+    #[test]
+    fn test_styles() {
+        assert_eq!(to_markdown(&bold("x")), "**x**");
+        assert_eq!(to_markdown(&italic("x")), "*x*");
+        assert_eq!(to_markdown(&underline("x")), "__x__");
+        assert_eq!(to_markdown(&strikethrough("x")), "~~x~~");
+        assert_eq!(to_markdown(&monospace("x")), "`x`");
+        assert_eq!(to_markdown(&bold(italic("x"))), "***x***");
+    }
+
+    #[test]
+    fn test_whitespace_outside_markers() {
+        assert_eq!(to_markdown(&format!("a{}b", bold(" x "))), "a **x** b");
+        assert_eq!(to_markdown(&bold(" ")), " ");
+    }
+
+    #[test]
+    fn test_text_escaped() {
+        assert_eq!(to_markdown("2*3_4"), "2\\*3\\_4");
+        assert_eq!(to_markdown(&bold("a*b")), "**a\\*b**");
+        assert_eq!(to_markdown(&monospace("a*`b")), "`a*'b`");
+    }
+
+    #[test]
+    fn test_colored_bold_merges() {
+        assert_eq!(to_markdown(&format!("{}{}", bold(c1("a")), bold(c2("b")))), "**ab**");
+    }
+}
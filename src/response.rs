@@ -0,0 +1,249 @@
+use crate::ffi::reinze_free_string;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+/// How the host should deliver a line.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageKind {
+    Privmsg = 0,
+    Notice = 1,
+    Action = 2,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub kind: MessageKind,
+    /// Where to send the line; `None` replies wherever the command came from.
+    pub target: Option<String>,
+    pub text: String,
+}
+
+/// Everything a plugin wants the host to send back, in order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Response {
+    pub messages: Vec<Message>,
+}
+
+impl Response {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn privmsg<T>(self, text: T) -> Self
+    where
+        T: ToString,
+    {
+        self.push(MessageKind::Privmsg, text)
+    }
+
+    pub fn notice<T>(self, text: T) -> Self
+    where
+        T: ToString,
+    {
+        self.push(MessageKind::Notice, text)
+    }
+
+    pub fn action<T>(self, text: T) -> Self
+    where
+        T: ToString,
+    {
+        self.push(MessageKind::Action, text)
+    }
+
+    /// Add one PRIVMSG per line.
+    pub fn lines<I, T>(mut self, lines: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: ToString,
+    {
+        for line in lines {
+            self = self.privmsg(line);
+        }
+        self
+    }
+
+    /// Send every message added so far to `target` instead of the origin.
+    pub fn to<T>(mut self, target: T) -> Self
+    where
+        T: ToString,
+    {
+        let target = target.to_string();
+        for message in self.messages.iter_mut() {
+            message.target = Some(target.clone());
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    fn push<T>(mut self, kind: MessageKind, text: T) -> Self
+    where
+        T: ToString,
+    {
+        self.messages.push(Message {
+            kind,
+            target: None,
+            text: text.to_string(),
+        });
+        self
+    }
+}
+
+impl From<Vec<String>> for Response {
+    fn from(lines: Vec<String>) -> Self {
+        Self::new().lines(lines)
+    }
+}
+
+/// A single `Message` as laid out for the host. `target` is null when the
+/// reply goes back to the origin.
+#[repr(C)]
+pub struct FfiMessage {
+    pub kind: MessageKind,
+    pub target: *mut c_char,
+    pub text: *mut c_char,
+}
+
+/// A `Response` as laid out for the host. It is owned by the library that
+/// built it and must be released with that library's `reinze_free_response`.
+#[repr(C)]
+pub struct FfiResponse {
+    pub messages: *mut FfiMessage,
+    pub len: usize,
+}
+
+impl FfiResponse {
+    pub fn empty() -> Self {
+        Response::new().into()
+    }
+
+    /// Copy the messages back out on the host side. The response still has
+    /// to be freed by whoever allocated it.
+    ///
+    /// # Safety
+    /// `self` must have been produced by `FfiResponse::from` and not freed yet.
+    pub unsafe fn to_response(&self) -> Response {
+        if self.messages.is_null() {
+            return Response::new();
+        }
+
+        let messages = std::slice::from_raw_parts(self.messages, self.len)
+            .iter()
+            .map(|m| Message {
+                kind: m.kind,
+                target: if m.target.is_null() {
+                    None
+                } else {
+                    Some(CStr::from_ptr(m.target).to_string_lossy().into_owned())
+                },
+                text: CStr::from_ptr(m.text).to_string_lossy().into_owned(),
+            })
+            .collect();
+
+        Response { messages }
+    }
+}
+
+impl From<Response> for FfiResponse {
+    fn from(response: Response) -> Self {
+        let messages: Box<[FfiMessage]> = response
+            .messages
+            .into_iter()
+            .map(|m| FfiMessage {
+                kind: m.kind,
+                target: m.target.map_or(std::ptr::null_mut(), into_raw),
+                text: into_raw(m.text),
+            })
+            .collect();
+
+        let len = messages.len();
+        let messages = Box::into_raw(messages) as *mut FfiMessage;
+
+        FfiResponse { messages, len }
+    }
+}
+
+/// Release an `FfiResponse` built by this library.
+///
+/// # Safety
+/// `response` must be a value returned by `plugin::respond` in this build,
+/// unmodified and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn reinze_free_response(response: FfiResponse) {
+    if response.messages.is_null() {
+        return;
+    }
+
+    let messages = Box::from_raw(std::ptr::slice_from_raw_parts_mut(response.messages, response.len));
+    for message in messages.iter() {
+        reinze_free_string(message.target);
+        reinze_free_string(message.text);
+    }
+}
+
+fn into_raw(s: String) -> *mut c_char {
+    CString::new(s.replace('\0', "")).unwrap_or_default().into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let response = Response::new().privmsg("one").notice("two").action("waves");
+        assert_eq!(response.messages.len(), 3);
+        assert_eq!(response.messages[0].kind, MessageKind::Privmsg);
+        assert_eq!(response.messages[1].kind, MessageKind::Notice);
+        assert_eq!(response.messages[2].kind, MessageKind::Action);
+        assert_eq!(response.messages[2].text, "waves");
+        assert!(response.messages.iter().all(|m| m.target.is_none()));
+    }
+
+    #[test]
+    fn test_lines_and_target() {
+        let response = Response::new().lines(vec!["a", "b"]).to("#channel").notice("c");
+        assert_eq!(response.messages[0].target.as_deref(), Some("#channel"));
+        assert_eq!(response.messages[1].target.as_deref(), Some("#channel"));
+        assert_eq!(response.messages[2].target, None);
+    }
+
+    #[test]
+    fn test_from_vec() {
+        let response = Response::from(vec!["x".to_string(), "y".to_string()]);
+        assert_eq!(response, Response::new().privmsg("x").privmsg("y"));
+    }
+
+    #[test]
+    fn test_ffi_round_trip() {
+        let response = Response::new().privmsg("hello").notice("secret").to("nick").action("dances");
+        let ffi = FfiResponse::from(response.clone());
+        assert_eq!(ffi.len, 3);
+
+        let back = unsafe { ffi.to_response() };
+        assert_eq!(back, response);
+
+        unsafe { reinze_free_response(ffi) };
+    }
+
+    #[test]
+    fn test_ffi_empty() {
+        let ffi = FfiResponse::empty();
+        assert_eq!(ffi.len, 0);
+        assert!(unsafe { ffi.to_response() }.is_empty());
+        unsafe { reinze_free_response(ffi) };
+    }
+
+    #[test]
+    fn test_free_null_response() {
+        unsafe {
+            reinze_free_response(FfiResponse {
+                messages: std::ptr::null_mut(),
+                len: 0,
+            })
+        };
+    }
+}
@@ -1,27 +1,91 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use mysql::prelude::Queryable;
 use mysql::{params, from_row};
 use regex::Regex;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime};
 
 use crate::database;
+use crate::hiscores::{Game, Hiscores};
+use crate::time::{self, Date, Since};
 
-/// Parse a duration string like "3d", "12h", "1w", "2w3d" into total hours.
-/// Supported units: h (hours), d (days = 24h), w (weeks = 168h). Combinable.
-pub fn parse_duration(s: &str) -> Result<u64> {
-    let re = Regex::new(r"^(?:(\d+)w)?(?:(\d+)d)?(?:(\d+)h)?$").unwrap();
-    let caps = re
-        .captures(s)
-        .context("invalid duration format, use e.g. @3d, @1w, @12h, @2w3d")?;
+static DATE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\d{4})-(\d{2})-(\d{2})$").unwrap());
 
-    let weeks: u64 = caps.get(1).map_or(0, |m| m.as_str().parse().unwrap_or(0));
-    let days: u64 = caps.get(2).map_or(0, |m| m.as_str().parse().unwrap_or(0));
-    let hours: u64 = caps.get(3).map_or(0, |m| m.as_str().parse().unwrap_or(0));
+static PART_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\d+)\s*([a-z]+)").unwrap());
 
-    let total = weeks * 168 + days * 24 + hours;
+// What may sit between the parts of a duration: "1w 2d", "1w, 2d", "1w and 2d".
+static GAP_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:\s*|\s*,\s*|\s+and\s+)$").unwrap());
+
+/// Parse how far back to look: a duration like "3d", "2w3d", "1 week",
+/// "3d 2w", "1y 2mo" or "90m", the word "yesterday", or a date like
+/// "2026-09-01".
+///
+/// Units: m (minutes), h (hours), d (days), w (weeks), mo (months = 30d),
+/// y (years = 365d), each also spelled out in full. Any order; each unit at
+/// most once.
+pub fn parse_duration(s: &str) -> Result<Since> {
+    let input = s.trim().to_lowercase();
+
+    if input == "yesterday" {
+        return Ok(Since::Ago(Duration::from_secs(time::DAY)));
+    }
+
+    if let Some(caps) = DATE_RE.captures(&input) {
+        let date = Date::new(caps[1].parse()?, caps[2].parse()?, caps[3].parse()?)?;
+        return Ok(Since::Date(date));
+    }
+
+    let invalid = || anyhow!("invalid duration format, use e.g. @3d, @1w, @12h, @2w3d, @1 month or @2026-09-01");
+
+    let mut seen = Vec::new();
+    let mut total: u64 = 0;
+    let mut end = 0;
+    for caps in PART_RE.captures_iter(&input) {
+        let part = caps.get(0).unwrap();
+        if !GAP_RE.is_match(&input[end..part.start()]) {
+            return Err(invalid());
+        }
+        end = part.end();
+
+        let unit = match &caps[2] {
+            "m" | "min" | "mins" | "minute" | "minutes" => time::MINUTE,
+            "h" | "hr" | "hrs" | "hour" | "hours" => time::HOUR,
+            "d" | "day" | "days" => time::DAY,
+            "w" | "wk" | "wks" | "week" | "weeks" => time::WEEK,
+            "mo" | "mos" | "month" | "months" => time::MONTH,
+            "y" | "yr" | "yrs" | "year" | "years" => time::YEAR,
+            _ => return Err(invalid()),
+        };
+        if seen.contains(&unit) {
+            bail!("ambiguous duration {:?}, {} given twice", s, &caps[2]);
+        }
+        seen.push(unit);
+
+        total = caps[1]
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(unit))
+            .and_then(|n| n.checked_add(total))
+            .context("duration is too long")?;
+    }
+
+    if seen.is_empty() || !input[end..].trim().is_empty() {
+        return Err(invalid());
+    }
     if total == 0 {
         bail!("duration must be greater than 0");
     }
-    Ok(total)
+    Ok(Since::Ago(Duration::from_secs(total)))
+}
+
+// The value bound to `:cutoff`: seconds since the epoch, compared through
+// `FROM_UNIXTIME` so a date means midnight UTC, as `Since::at` reads it,
+// whatever the database's time zone.
+fn cutoff(since: Since, now: SystemTime) -> i64 {
+    since
+        .at(now)
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
 }
 
 /// Store a snapshot of raw hiscores data for a player.
@@ -38,26 +102,131 @@ pub fn save_snapshot(game: &str, mode: &str, rsn: &str, data: &str) -> Result<()
     Ok(())
 }
 
-/// Retrieve the most recent snapshot at least `hours_ago` hours old.
+/// Retrieve the most recent snapshot taken no later than `since`.
 pub fn get_snapshot(
     game: &str,
     mode: &str,
     rsn: &str,
-    hours_ago: u64,
+    since: Since,
 ) -> Result<Option<String>> {
     let mut conn = database::connect()
         .map_err(|e| anyhow::anyhow!("database connection failed: {}", e))?;
 
     let result: Option<String> = conn
         .exec_first(
-            "SELECT data FROM hiscores_snapshots WHERE game = :game AND mode = :mode AND rsn = :rsn AND snapshot_at <= DATE_SUB(NOW(), INTERVAL :hours HOUR) ORDER BY snapshot_at DESC LIMIT 1",
-            params! { "game" => game, "mode" => mode, "rsn" => rsn, "hours" => hours_ago },
+            "SELECT data FROM hiscores_snapshots WHERE game = :game AND mode = :mode AND rsn = :rsn AND snapshot_at <= FROM_UNIXTIME(:cutoff) ORDER BY snapshot_at DESC LIMIT 1",
+            params! { "game" => game, "mode" => mode, "rsn" => rsn, "cutoff" => cutoff(since, SystemTime::now()) },
         )
         .context("failed to query snapshot")?;
 
     Ok(result)
 }
 
+/// A stored snapshot and when it was taken.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub data: String,
+    pub taken_at: SystemTime,
+}
+
+impl Snapshot {
+    pub fn hiscores(&self, game: Game) -> Result<Hiscores> {
+        Hiscores::parse(game, &self.data)
+    }
+}
+
+/// The snapshot closest to a requested time, on or before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Boundary {
+    pub snapshot: Snapshot,
+    pub requested: SystemTime,
+    /// How long before `requested` the snapshot was taken.
+    pub distance: Duration,
+}
+
+impl Boundary {
+    fn from_row(data: String, taken_at: i64, requested: i64) -> Self {
+        let at = |secs: i64| SystemTime::UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64);
+
+        Boundary {
+            snapshot: Snapshot {
+                data,
+                taken_at: at(taken_at),
+            },
+            requested: at(requested),
+            distance: Duration::from_secs(requested.saturating_sub(taken_at).max(0) as u64),
+        }
+    }
+}
+
+/// The snapshots bounding a range. Either end is `None` when there is no
+/// snapshot that old.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotRange {
+    pub from: Option<Boundary>,
+    pub to: Option<Boundary>,
+}
+
+impl SnapshotRange {
+    /// Whether either end is missing or further than `tolerance` from the
+    /// time asked for, so the result may not cover the range the user meant.
+    pub fn is_sparse(&self, tolerance: Duration) -> bool {
+        [&self.from, &self.to]
+            .iter()
+            .any(|b| b.as_ref().is_none_or(|b| b.distance > tolerance))
+    }
+}
+
+/// Retrieve the most recent snapshot taken no later than `since`, with how
+/// far it is from that time.
+pub fn get_snapshot_at(
+    game: &str,
+    mode: &str,
+    rsn: &str,
+    since: Since,
+) -> Result<Option<Boundary>> {
+    boundary_at(game, mode, rsn, cutoff(since, SystemTime::now()))
+}
+
+fn boundary_at(game: &str, mode: &str, rsn: &str, cutoff: i64) -> Result<Option<Boundary>> {
+    let mut conn = database::connect()
+        .map_err(|e| anyhow::anyhow!("database connection failed: {}", e))?;
+
+    let row: Option<(String, i64)> = conn
+        .exec_first(
+            "SELECT data, CAST(UNIX_TIMESTAMP(snapshot_at) AS SIGNED) FROM hiscores_snapshots WHERE game = :game AND mode = :mode AND rsn = :rsn AND snapshot_at <= FROM_UNIXTIME(:cutoff) ORDER BY snapshot_at DESC LIMIT 1",
+            params! { "game" => game, "mode" => mode, "rsn" => rsn, "cutoff" => cutoff },
+        )
+        .context("failed to query snapshot")?;
+
+    Ok(row.map(|(data, taken_at)| Boundary::from_row(data, taken_at, cutoff)))
+}
+
+/// Retrieve the snapshots bounding `from..to`: for each end, the closest
+/// one taken on or before it. Use `Since::Ago(Duration::ZERO)` for now.
+pub fn get_snapshot_range(
+    game: &str,
+    mode: &str,
+    rsn: &str,
+    from: Since,
+    to: Since,
+) -> Result<SnapshotRange> {
+    let now = SystemTime::now();
+    check_range(from, to, now)?;
+
+    Ok(SnapshotRange {
+        from: boundary_at(game, mode, rsn, cutoff(from, now))?,
+        to: boundary_at(game, mode, rsn, cutoff(to, now))?,
+    })
+}
+
+fn check_range(from: Since, to: Since, now: SystemTime) -> Result<()> {
+    if from.at(now) > to.at(now) {
+        bail!("invalid range, {} is after {}", from, to);
+    }
+    Ok(())
+}
+
 /// Retrieve the most recent snapshot regardless of age.
 pub fn get_latest_snapshot(
     game: &str,
@@ -77,6 +246,25 @@ pub fn get_latest_snapshot(
     Ok(result)
 }
 
+/// Store a player's hiscores under their game.
+pub fn save_hiscores(mode: &str, rsn: &str, hiscores: &Hiscores) -> Result<()> {
+    save_snapshot(hiscores.game.name(), mode, rsn, &hiscores.to_csv())
+}
+
+/// `get_snapshot`, parsed.
+pub fn get_hiscores(game: Game, mode: &str, rsn: &str, since: Since) -> Result<Option<Hiscores>> {
+    get_snapshot(game.name(), mode, rsn, since)?
+        .map(|data| Hiscores::parse(game, &data))
+        .transpose()
+}
+
+/// `get_latest_snapshot`, parsed.
+pub fn get_latest_hiscores(game: Game, mode: &str, rsn: &str) -> Result<Option<Hiscores>> {
+    get_latest_snapshot(game.name(), mode, rsn)?
+        .map(|data| Hiscores::parse(game, &data))
+        .transpose()
+}
+
 /// Get all distinct RSNs tracked for a given game (for scheduled snapshots).
 pub fn get_tracked_players(game: &str) -> Result<Vec<String>> {
     let mut conn = database::connect()
@@ -89,36 +277,40 @@ pub fn get_tracked_players(game: &str) -> Result<Vec<String>> {
         )
         .context("failed to query tracked players")?;
 
-    Ok(rows.into_iter().map(|r| from_row(r)).collect())
+    Ok(rows.into_iter().map(from_row).collect())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn hours(n: u64) -> Since {
+        Since::Ago(Duration::from_secs(n * time::HOUR))
+    }
+
     #[test]
     fn test_parse_hours() {
-        assert_eq!(parse_duration("12h").unwrap(), 12);
+        assert_eq!(parse_duration("12h").unwrap(), hours(12));
     }
 
     #[test]
     fn test_parse_days() {
-        assert_eq!(parse_duration("3d").unwrap(), 72);
+        assert_eq!(parse_duration("3d").unwrap(), hours(72));
     }
 
     #[test]
     fn test_parse_weeks() {
-        assert_eq!(parse_duration("1w").unwrap(), 168);
+        assert_eq!(parse_duration("1w").unwrap(), hours(168));
     }
 
     #[test]
     fn test_parse_combined() {
-        assert_eq!(parse_duration("2w3d").unwrap(), 408);
+        assert_eq!(parse_duration("2w3d").unwrap(), hours(408));
     }
 
     #[test]
     fn test_parse_all_units() {
-        assert_eq!(parse_duration("1w1d1h").unwrap(), 193);
+        assert_eq!(parse_duration("1w1d1h").unwrap(), hours(193));
     }
 
     #[test]
@@ -134,7 +326,120 @@ mod tests {
     #[test]
     fn test_parse_invalid() {
         assert!(parse_duration("abc").is_err());
-        assert!(parse_duration("10m").is_err());
         assert!(parse_duration("5s").is_err());
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("3d foo").is_err());
+        assert!(parse_duration("3d-2h").is_err());
+        assert!(parse_duration("tomorrow").is_err());
+    }
+
+    #[test]
+    fn test_parse_minutes_months_years() {
+        let minutes = |n: u64| Since::Ago(Duration::from_secs(n * time::MINUTE));
+        assert_eq!(parse_duration("10m").unwrap(), minutes(10));
+        assert_eq!(parse_duration("1h30m").unwrap(), minutes(90));
+        assert_eq!(parse_duration("2mo").unwrap(), hours(2 * 30 * 24));
+        assert_eq!(parse_duration("1y").unwrap(), hours(365 * 24));
+    }
+
+    #[test]
+    fn test_parse_any_order_and_words() {
+        assert_eq!(parse_duration("3d2w").unwrap(), hours(408));
+        assert_eq!(parse_duration(" 2w 3d ").unwrap(), hours(408));
+        assert_eq!(parse_duration("2 weeks, 3 days").unwrap(), hours(408));
+        assert_eq!(parse_duration("2 Weeks and 3 Days").unwrap(), hours(408));
+        assert_eq!(parse_duration("1 week").unwrap(), hours(168));
+        assert_eq!(parse_duration("1 month").unwrap(), parse_duration("1mo").unwrap());
+        assert_eq!(parse_duration("yesterday").unwrap(), hours(24));
+    }
+
+    #[test]
+    fn test_parse_repeated_unit() {
+        let err = parse_duration("1d 2d").unwrap_err().to_string();
+        assert!(err.contains("given twice"), "{}", err);
+        assert!(parse_duration("1d 2 days").is_err());
+    }
+
+    #[test]
+    fn test_parse_overflow() {
+        assert!(parse_duration("99999999999999999999y").is_err());
+        assert!(parse_duration("999999999999999y").is_err());
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_duration("2026-09-01").unwrap(), Since::Date(Date::new(2026, 9, 1).unwrap()));
+        assert!(parse_duration("2026-02-30").is_err());
+        assert!(parse_duration("2026-9-1").is_err());
+    }
+
+    #[test]
+    fn test_cutoff() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(10_000);
+        assert_eq!(cutoff(hours(2), now), 10_000 - 7200);
+        assert_eq!(cutoff(hours(3), now), 0);
+
+        // Midnight UTC, not the database's midnight.
+        assert_eq!(cutoff(parse_duration("2026-09-01").unwrap(), now), 1_788_220_800);
+    }
+
+    #[test]
+    fn test_snapshot_hiscores() {
+        let snapshot = Snapshot {
+            data: "1,99,13034431\n-1,-1\n".to_string(),
+            taken_at: SystemTime::UNIX_EPOCH,
+        };
+        let hiscores = snapshot.hiscores(Game::Osrs).unwrap();
+        assert_eq!(hiscores.overall().xp, Some(13_034_431));
+        assert_eq!(hiscores.activities.len(), 1);
+    }
+
+    #[test]
+    fn test_boundary_from_row() {
+        let boundary = Boundary::from_row("data".to_string(), 1_000, 4_600);
+        assert_eq!(boundary.snapshot.data, "data");
+        assert_eq!(boundary.snapshot.taken_at, SystemTime::UNIX_EPOCH + Duration::from_secs(1_000));
+        assert_eq!(boundary.requested, SystemTime::UNIX_EPOCH + Duration::from_secs(4_600));
+        assert_eq!(boundary.distance, Duration::from_secs(3_600));
+    }
+
+    #[test]
+    fn test_is_sparse() {
+        let hour = Duration::from_secs(time::HOUR);
+        let close = Boundary::from_row(String::new(), 0, 60);
+        let far = Boundary::from_row(String::new(), 0, 2 * time::DAY as i64);
+
+        let range = SnapshotRange {
+            from: Some(close.clone()),
+            to: Some(close.clone()),
+        };
+        assert!(!range.is_sparse(hour));
+
+        let range = SnapshotRange {
+            from: Some(far),
+            to: Some(close.clone()),
+        };
+        assert!(range.is_sparse(hour));
+        assert!(!range.is_sparse(Duration::from_secs(3 * time::DAY)));
+
+        let range = SnapshotRange {
+            from: None,
+            to: Some(close),
+        };
+        assert!(range.is_sparse(hour));
+    }
+
+    #[test]
+    fn test_check_range() {
+        let now = Date::new(2026, 9, 20).unwrap().to_system_time();
+        let sept = |day| Since::Date(Date::new(2026, 9, day).unwrap());
+
+        assert!(check_range(sept(1), sept(15), now).is_ok());
+        assert!(check_range(parse_duration("1w").unwrap(), Since::Ago(Duration::ZERO), now).is_ok());
+        assert!(check_range(sept(1), parse_duration("1d").unwrap(), now).is_ok());
+
+        let err = check_range(sept(15), sept(1), now).unwrap_err().to_string();
+        assert!(err.contains("2026-09-15 is after 2026-09-01"), "{}", err);
+        assert!(check_range(parse_duration("1d").unwrap(), parse_duration("1w").unwrap(), now).is_err());
     }
 }
@@ -1,11 +1,51 @@
 use crate::author::Author;
-use crate::Colors;
+use crate::locale::{self, Locale};
+use crate::response::Response;
+use crate::theme::{self, Scope, Theme, ThemeOverrides};
+use crate::{Colors, PluginContext};
+use anyhow::bail;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::c_char;
+
+/// Reasons a `PluginContext` could not be turned into a `Source`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContextError {
+    NullPointer(&'static str),
+    InvalidUtf8(&'static str),
+    IncompatibleAbi(String),
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContextError::NullPointer(field) => write!(f, "plugin context field `{}` is null", field),
+            ContextError::InvalidUtf8(field) => {
+                write!(f, "plugin context field `{}` is not valid UTF-8", field)
+            }
+            ContextError::IncompatibleAbi(reason) => write!(f, "incompatible plugin context: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ContextError {}
 
 pub struct Source {
     pub rsn_n: String,
     pub author: Author,
     pub command: String,
     pub query: String,
+    /// Where the command came from; empty if the host didn't say.
+    pub network: String,
+    pub channel: String,
+    /// The theme the host resolved, if it sent one; see `theme`.
+    pub host_theme: Option<ThemeOverrides>,
+    /// How to store a theme in the host; `None` stores it locally.
+    pub set_theme: Option<unsafe extern "C" fn(*const c_char, *const c_char) -> bool>,
+    /// The locale the host resolved, if it sent one; see `locale`.
+    pub host_locale: Option<Locale>,
+    /// How to store a locale in the host; `None` stores it locally.
+    pub set_locale: Option<unsafe extern "C" fn(*const c_char, *const c_char) -> bool>,
 }
 
 impl Source {
@@ -18,9 +58,63 @@ impl Source {
             author,
             command: command.to_string(),
             query: query.to_string(),
+            network: String::new(),
+            channel: String::new(),
+            host_theme: None,
+            set_theme: None,
+            host_locale: None,
+            set_locale: None,
         }
     }
 
+    /// Build a `Source` from the context the host passed in, checking every
+    /// pointer for null before reading it. `rsn_n` defaults to "0".
+    ///
+    /// # Safety
+    /// Every non-null string pointer in `ctx` that its header covers must
+    /// point to a valid nul-terminated string, and `color`, `free_color`,
+    /// `set_theme` and `set_locale` must stay callable for as long as the
+    /// returned `Source` is used.
+    pub unsafe fn from_context(ctx: &PluginContext) -> Result<Self, ContextError> {
+        ctx.validate()
+            .map_err(|e| ContextError::IncompatibleAbi(e.to_string()))?;
+
+        let command = read_field(ctx.cmd, "cmd")?;
+        let query = read_field(ctx.param, "param")?;
+        let author = read_field(ctx.author, "author")?;
+
+        let (network, channel) = ctx.origin();
+        let network = read_optional_field(network, "network")?;
+        let channel = read_optional_field(channel, "channel")?;
+
+        let mut author = Author::create(author, ctx.color);
+        author.free_color = ctx.free_color();
+        author.plain = ctx.plain();
+
+        let theme = read_optional_field(ctx.theme(), "theme")?;
+        let locale = read_optional_field(ctx.locale(), "locale")?;
+
+        let mut source = Self::create("0".to_string(), author, command, query);
+        source.network = network;
+        source.channel = channel;
+        source.set_theme = ctx.set_theme();
+        if !theme.is_empty() {
+            match theme.parse() {
+                Ok(overrides) => source.host_theme = Some(overrides),
+                Err(e) => log::error!("Invalid theme from host: {}", e),
+            }
+        }
+        source.set_locale = ctx.set_locale();
+        if !locale.is_empty() {
+            match locale.parse() {
+                Ok(locale) => source.host_locale = Some(locale),
+                Err(e) => log::error!("Invalid locale from host: {}", e),
+            }
+        }
+
+        Ok(source)
+    }
+
     pub fn c1<T>(&self, s: T) -> String
     where
         T: ToString,
@@ -49,6 +143,87 @@ impl Source {
         self.author.p(s)
     }
 
+    /// Text in the user's "good" color, e.g. for gains.
+    pub fn positive<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        self.theme().positive(s)
+    }
+
+    /// Text in the user's "bad" color, e.g. for losses.
+    pub fn negative<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        self.theme().negative(s)
+    }
+
+    /// Text in the user's color for no change.
+    pub fn neutral<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        self.theme().neutral(s)
+    }
+
+    /// `Theme::delta` in this user's theme.
+    pub fn delta(&self, n: i64) -> String {
+        self.theme().delta(n)
+    }
+
+    /// The theme for this user in this channel and network: the one the host
+    /// resolved, or, without one, resolved from this build's own cache.
+    pub fn theme(&self) -> Theme {
+        let colors = self.get_colors();
+        match &self.host_theme {
+            Some(overrides) => Theme {
+                plain: colors.plain,
+                ..Theme::default().merge(overrides)
+            },
+            None => theme::resolve(&self.author.host, &self.network, &self.channel, Some(&colors)),
+        }
+    }
+
+    /// Store a theme for `scope` (empty overrides clear it), through the host
+    /// when it provides a way, so every plugin sees it.
+    pub fn set_theme(&self, scope: &Scope, overrides: ThemeOverrides) -> anyhow::Result<()> {
+        let Some(set_theme) = self.set_theme else {
+            return theme::set(scope, overrides);
+        };
+
+        let key = CString::new(scope.key())?;
+        let value = CString::new(overrides.to_string())?;
+        // Safe because `from_context` requires `set_theme` to stay callable.
+        if !unsafe { set_theme(key.as_ptr(), value.as_ptr()) } {
+            bail!("the host could not save the theme for {}", scope.key());
+        }
+        Ok(())
+    }
+
+    /// The locale for this user in this channel and network: the one the
+    /// host resolved, or, without one, resolved from this build's own cache.
+    pub fn locale(&self) -> Locale {
+        self.host_locale
+            .unwrap_or_else(|| locale::resolve(&self.author.host, &self.network, &self.channel))
+    }
+
+    /// Store a locale for `scope` (`None` clears it), through the host when it
+    /// provides a way, so every plugin sees it.
+    pub fn set_locale(&self, scope: &Scope, locale: Option<Locale>) -> anyhow::Result<()> {
+        let Some(set_locale) = self.set_locale else {
+            return locale::set(scope, locale);
+        };
+
+        let key = CString::new(scope.key())?;
+        let value = CString::new(locale.map_or("", |l| l.name))?;
+        // Safe because `from_context` requires `set_locale` to stay callable.
+        if !unsafe { set_locale(key.as_ptr(), value.as_ptr()) } {
+            bail!("the host could not save the locale for {}", scope.key());
+        }
+        Ok(())
+    }
+
     pub fn get_colors(&self) -> Colors {
         unsafe { self.author.colors() }
     }
@@ -61,6 +236,29 @@ impl Source {
         self.author.clear_colors()
     }
 
+    /// Start a response with a PRIVMSG back to wherever the command came from.
+    pub fn reply<T>(&self, s: T) -> Response
+    where
+        T: ToString,
+    {
+        Response::new().privmsg(s)
+    }
+
+    /// Start a response with a NOTICE to the author only.
+    pub fn notice<T>(&self, s: T) -> Response
+    where
+        T: ToString,
+    {
+        Response::new().notice(s).to(&self.author.nick)
+    }
+
+    pub fn action<T>(&self, s: T) -> Response
+    where
+        T: ToString,
+    {
+        Response::new().action(s)
+    }
+
     pub fn not_found(&self, v: Vec<String>) -> String {
         if v.is_empty() {
             self.c2("Not found")
@@ -70,10 +268,35 @@ impl Source {
     }
 }
 
+/// # Safety
+/// `ptr` must be null or point to a valid nul-terminated string.
+unsafe fn read_field(ptr: *const c_char, field: &'static str) -> Result<String, ContextError> {
+    if ptr.is_null() {
+        return Err(ContextError::NullPointer(field));
+    }
+
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map(|s| s.to_owned())
+        .map_err(|_| ContextError::InvalidUtf8(field))
+}
+
+/// # Safety
+/// Same as `read_field`.
+unsafe fn read_optional_field(ptr: *const c_char, field: &'static str) -> Result<String, ContextError> {
+    if ptr.is_null() {
+        Ok(String::new())
+    } else {
+        read_field(ptr, field)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::ColorResult;
+    use crate::IrcColor;
+    use std::ffi::CString;
 
     extern "C" fn stub_color(_host: *const std::os::raw::c_char, _colors: *const std::os::raw::c_char) -> ColorResult {
         ColorResult::default()
@@ -132,8 +355,8 @@ mod tests {
     fn test_get_colors_returns_defaults() {
         let source = make_source("0", "nick!ident@host", "cmd", "q");
         let colors = source.get_colors();
-        assert_eq!(colors.c1, "14");
-        assert_eq!(colors.c2, "04");
+        assert_eq!(colors.c1, IrcColor::new(14).unwrap());
+        assert_eq!(colors.c2, IrcColor::new(4).unwrap());
     }
 
     #[test]
@@ -151,4 +374,220 @@ mod tests {
         assert_eq!(source.rsn_n, "1");
         assert_eq!(source.command, "stats");
     }
+
+    #[test]
+    fn test_from_context() {
+        let cmd = CString::new("stats").unwrap();
+        let param = CString::new("zezima").unwrap();
+        let author = CString::new("nick!~ident@host").unwrap();
+        let ctx = PluginContext::new(cmd.as_ptr(), param.as_ptr(), author.as_ptr(), stub_color);
+
+        let source = unsafe { Source::from_context(&ctx) }.unwrap();
+        assert_eq!(source.rsn_n, "0");
+        assert_eq!(source.command, "stats");
+        assert_eq!(source.query, "zezima");
+        assert_eq!(source.author.nick, "nick");
+        assert_eq!(source.author.host, "ident@host");
+        assert_eq!(source.l("ok"), "\x0314[\x0304ok\x0314]");
+    }
+
+    #[test]
+    fn test_from_context_null_pointer() {
+        let cmd = CString::new("stats").unwrap();
+        let author = CString::new("nick!ident@host").unwrap();
+        let ctx = PluginContext::new(cmd.as_ptr(), std::ptr::null(), author.as_ptr(), stub_color);
+
+        let err = unsafe { Source::from_context(&ctx) }.err().unwrap();
+        assert_eq!(err, ContextError::NullPointer("param"));
+        assert_eq!(err.to_string(), "plugin context field `param` is null");
+    }
+
+    #[test]
+    fn test_from_context_invalid_utf8() {
+        let cmd = CString::new(vec![0x73, 0xff, 0xfe]).unwrap();
+        let param = CString::new("").unwrap();
+        let author = CString::new("nick!ident@host").unwrap();
+        let ctx = PluginContext::new(cmd.as_ptr(), param.as_ptr(), author.as_ptr(), stub_color);
+
+        assert_eq!(unsafe { Source::from_context(&ctx) }.err(), Some(ContextError::InvalidUtf8("cmd")));
+    }
+
+    #[test]
+    fn test_from_context_bad_abi() {
+        let cmd = CString::new("stats").unwrap();
+        let mut ctx = PluginContext::new(cmd.as_ptr(), cmd.as_ptr(), cmd.as_ptr(), stub_color);
+        ctx.abi_version = 1;
+
+        assert!(matches!(
+            unsafe { Source::from_context(&ctx) },
+            Err(ContextError::IncompatibleAbi(_))
+        ));
+    }
+
+    #[test]
+    fn test_reply() {
+        let source = make_source("0", "nick!ident@host", "cmd", "q");
+        let response = source.reply(source.l("Stats")).privmsg("second");
+        assert_eq!(response.messages.len(), 2);
+        assert_eq!(response.messages[0].text, "\x0314[\x0304Stats\x0314]");
+        assert_eq!(response.messages[0].target, None);
+    }
+
+    #[test]
+    fn test_notice_targets_author() {
+        let source = make_source("0", "nick!ident@host", "cmd", "q");
+        let response = source.notice("psst");
+        assert_eq!(response.messages[0].kind, crate::response::MessageKind::Notice);
+        assert_eq!(response.messages[0].target.as_deref(), Some("nick"));
+    }
+
+    #[test]
+    fn test_from_context_origin() {
+        let cmd = CString::new("gains").unwrap();
+        let param = CString::new("zezima").unwrap();
+        let author = CString::new("nick!ident@host").unwrap();
+        let network = CString::new("Rizon").unwrap();
+        let channel = CString::new("#rs").unwrap();
+        let ctx = PluginContext::new(cmd.as_ptr(), param.as_ptr(), author.as_ptr(), stub_color)
+            .with_origin(network.as_ptr(), channel.as_ptr());
+
+        let source = unsafe { Source::from_context(&ctx) }.unwrap();
+        assert_eq!(source.network, "Rizon");
+        assert_eq!(source.channel, "#rs");
+
+        let ctx = PluginContext::new(cmd.as_ptr(), param.as_ptr(), author.as_ptr(), stub_color);
+        let source = unsafe { Source::from_context(&ctx) }.unwrap();
+        assert_eq!(source.network, "");
+        assert_eq!(source.channel, "");
+    }
+
+    #[test]
+    fn test_theme() {
+        let mut source = make_source("0", "nick!ident@source-theme", "cmd", "q");
+        source.network = "source-theme-net".to_string();
+        crate::theme::upsert(
+            &crate::theme::Scope::Network("source-theme-net".to_string()),
+            "positive=09".parse().unwrap(),
+        );
+
+        assert_eq!(source.theme().positive("+5"), "\x0309+5");
+        assert_eq!(source.theme().negative("-5"), "\x0305-5");
+        assert_eq!(source.theme().l("x"), source.l("x"));
+    }
+
+    #[test]
+    fn test_semantic_colors() {
+        let source = make_source("0", "nick!ident@semantic", "cmd", "q");
+        assert_eq!(source.positive("up"), crate::c4("up"));
+        assert_eq!(source.negative("down"), crate::c3("down"));
+        assert_eq!(source.neutral("same"), crate::c5("same"));
+    }
+
+    #[test]
+    fn test_delta() {
+        let source = make_source("0", "nick!ident@delta", "cmd", "q");
+        assert_eq!(source.delta(1234), "\x0303+1,234");
+        assert_eq!(source.delta(-567), "\x0305-567");
+        assert_eq!(source.delta(0), "\x03070");
+        assert_eq!(source.delta(-1_000_000), "\x0305-1,000,000");
+    }
+
+    #[test]
+    fn test_plain_mode() {
+        let cmd = CString::new("cmd").unwrap();
+        let param = CString::new("q").unwrap();
+        let author = CString::new("nick!ident@plain").unwrap();
+        let ctx = PluginContext::new(cmd.as_ptr(), param.as_ptr(), author.as_ptr(), stub_color).with_plain(true);
+
+        let source = unsafe { Source::from_context(&ctx) }.unwrap();
+        assert_eq!(source.l("Stats"), "[Stats]");
+        assert_eq!(source.delta(-567), "-567");
+        assert_eq!(source.not_found(vec![]), "Not found");
+        assert_eq!(source.not_found(vec!["a".to_string(), "b".to_string()]), "a | b");
+    }
+
+    #[test]
+    fn test_theme_from_host() {
+        let cmd = CString::new("gains").unwrap();
+        let author = CString::new("nick!ident@host-theme").unwrap();
+        let theme = CString::new(Theme::default().merge(&"positive=09".parse().unwrap()).to_overrides().to_string()).unwrap();
+        let ctx = PluginContext::new(cmd.as_ptr(), cmd.as_ptr(), author.as_ptr(), stub_color).with_theme(theme.as_ptr());
+
+        let source = unsafe { Source::from_context(&ctx) }.unwrap();
+        assert_eq!(source.delta(5), "\x0309+5");
+        assert_eq!(source.delta(-5), "\x0305-5");
+
+        let ctx = ctx.with_plain(true);
+        assert_eq!(unsafe { Source::from_context(&ctx) }.unwrap().delta(5), "+5");
+    }
+
+    static STORED: std::sync::Mutex<Vec<(String, String)>> = std::sync::Mutex::new(Vec::new());
+
+    unsafe extern "C" fn stub_set_theme(scope: *const c_char, theme: *const c_char) -> bool {
+        let read = |p| CStr::from_ptr(p).to_str().unwrap().to_string();
+        STORED.lock().unwrap().push((read(scope), read(theme)));
+        true
+    }
+
+    #[test]
+    fn test_set_theme_through_host() {
+        let mut source = make_source("0", "nick!ident@set-theme", "cmd", "q");
+        source.set_theme = Some(stub_set_theme);
+
+        let scope = Scope::Network("Rizon".to_string());
+        source.set_theme(&scope, "positive=09".parse().unwrap()).unwrap();
+        assert!(STORED
+            .lock()
+            .unwrap()
+            .contains(&("network:rizon".to_string(), "positive=09".to_string())));
+        // Only the host's copy changes.
+        assert!(theme::get(&scope).is_empty());
+    }
+
+    #[test]
+    fn test_locale_from_host() {
+        let cmd = CString::new("ge").unwrap();
+        let author = CString::new("nick!ident@host-locale").unwrap();
+        let locale = CString::new("de").unwrap();
+        let ctx = PluginContext::new(cmd.as_ptr(), cmd.as_ptr(), author.as_ptr(), stub_color).with_locale(locale.as_ptr());
+
+        let source = unsafe { Source::from_context(&ctx) }.unwrap();
+        assert_eq!(source.locale().name, "de");
+        assert_eq!(source.locale().commas_int(1_234), "1.234");
+
+        let ctx = PluginContext::new(cmd.as_ptr(), cmd.as_ptr(), author.as_ptr(), stub_color);
+        assert_eq!(unsafe { Source::from_context(&ctx) }.unwrap().locale(), locale::EN);
+    }
+
+    unsafe extern "C" fn stub_set_locale(scope: *const c_char, locale: *const c_char) -> bool {
+        let read = |p| CStr::from_ptr(p).to_str().unwrap().to_string();
+        STORED.lock().unwrap().push((read(scope), read(locale)));
+        true
+    }
+
+    #[test]
+    fn test_set_locale_through_host() {
+        let mut source = make_source("0", "nick!ident@set-locale", "cmd", "q");
+        source.set_locale = Some(stub_set_locale);
+
+        let scope = Scope::Host("ident@set-locale".to_string());
+        source.set_locale(&scope, Some("pt".parse().unwrap())).unwrap();
+        source.set_locale(&scope, None).unwrap();
+
+        let stored = STORED.lock().unwrap();
+        assert!(stored.contains(&("host:ident@set-locale".to_string(), "pt".to_string())));
+        assert!(stored.contains(&("host:ident@set-locale".to_string(), String::new())));
+        assert_eq!(locale::get(&scope), None);
+    }
+
+    #[test]
+    fn test_delta_respects_theme() {
+        let source = make_source("0", "nick!ident@delta-themed", "cmd", "q");
+        crate::theme::upsert(
+            &crate::theme::Scope::Host("ident@delta-themed".to_string()),
+            "positive=09,negative=04".parse().unwrap(),
+        );
+        assert_eq!(source.delta(5), "\x0309+5");
+        assert_eq!(source.delta(-5), "\x0304-5");
+    }
 }
@@ -0,0 +1,248 @@
+use crate::style::parse::{control_len, final_style};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Maximum length of an IRC line in bytes, including the trailing CRLF.
+pub const IRC_LINE_LIMIT: usize = 512;
+
+// A `c1(" | ")`-style separator, optionally preceded by its color code.
+static SEPARATOR_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:\x03\d{1,2}(?:,\d{1,2})?)? \| ").unwrap());
+
+/// Bytes the server adds around the text of a PRIVMSG when relaying it:
+/// `:<prefix> PRIVMSG <target> :<text>\r\n`, where `prefix` is the bot's
+/// own `nick!ident@host`.
+pub fn privmsg_overhead(prefix: &str, target: &str) -> usize {
+    ":".len() + prefix.len() + " PRIVMSG ".len() + target.len() + " :".len() + "\r\n".len()
+}
+
+/// Split `text` so that each PRIVMSG to `target` fits in a single IRC line.
+pub fn split_privmsg(text: &str, prefix: &str, target: &str) -> Vec<String> {
+    split_message(text, IRC_LINE_LIMIT.saturating_sub(privmsg_overhead(prefix, target)))
+}
+
+/// Split `text` into lines of at most `max_bytes` bytes.
+///
+/// Breaks at ` | ` separators where possible (dropping the separator), then
+/// at whitespace, and only as a last resort mid-word. Never breaks inside a
+/// formatting code. Each continuation line starts by re-emitting the color
+/// and formatting that were active where the previous line stopped.
+pub fn split_message(text: &str, max_bytes: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+
+    while start < text.len() {
+        let rest = &text[start..];
+        let prefix = if start > 0 {
+            let mut style = final_style(&text[..start]);
+            // The line sets its own color, so only carry over the toggles.
+            if starts_with_color(rest) {
+                style.fg = None;
+                style.bg = None;
+            }
+            style.prefix()
+        } else {
+            String::new()
+        };
+        let budget = max_bytes.saturating_sub(prefix.len()).max(1);
+
+        if rest.len() <= budget {
+            lines.push(format!("{}{}", prefix, rest));
+            break;
+        }
+
+        let (end, next) = find_break(rest, budget);
+        let line = &rest[..end];
+        if !line.is_empty() {
+            lines.push(format!("{}{}", prefix, line));
+        }
+        start += next;
+    }
+
+    lines
+}
+
+/// Where to end the current line, and where the next one starts.
+fn find_break(rest: &str, budget: usize) -> (usize, usize) {
+    if let Some(sep) = SEPARATOR_RE
+        .find_iter(rest)
+        .take_while(|m| m.start() <= budget)
+        .filter(|m| m.start() > 0)
+        .last()
+    {
+        return (sep.start(), sep.end());
+    }
+
+    let limit = safe_boundary(rest, budget);
+    // A space right at the limit is fine to break on; it gets dropped.
+    let search = if rest.as_bytes().get(limit) == Some(&b' ') {
+        limit + 1
+    } else {
+        limit
+    };
+    if let Some(space) = rest[..search].rfind(' ').filter(|&i| i > 0) {
+        return (space, space + 1);
+    }
+
+    // One very long word; break it wherever it fits. Always make progress,
+    // even if the budget is smaller than a single character.
+    let end = if limit == 0 {
+        rest.chars().next().map_or(rest.len(), char::len_utf8)
+    } else {
+        limit
+    };
+    (end, end)
+}
+
+/// The largest position `<= pos` that is a char boundary and not inside a
+/// formatting code.
+fn safe_boundary(s: &str, pos: usize) -> usize {
+    let mut pos = pos.min(s.len());
+    while !s.is_char_boundary(pos) {
+        pos -= 1;
+    }
+
+    // The longest code is `\x04RRGGBB,RRGGBB`.
+    let mut window = pos.saturating_sub(14);
+    while !s.is_char_boundary(window) {
+        window -= 1;
+    }
+    if let Some(i) = s[window..pos].rfind(['\x03', '\x04']).map(|i| i + window) {
+        if i + control_len(&s[i..]) > pos {
+            pos = i;
+        }
+    }
+
+    pos
+}
+
+fn starts_with_color(s: &str) -> bool {
+    (s.starts_with('\x03') || s.starts_with('\x04')) && control_len(s) > 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{c1, c2, l};
+
+    #[test]
+    fn test_privmsg_overhead() {
+        // ":bot!b@h PRIVMSG #c :" + "\r\n"
+        assert_eq!(privmsg_overhead("bot!b@h", "#c"), 23);
+    }
+
+    #[test]
+    fn test_short_message_untouched() {
+        let text = format!("{}{}{}", l("Stats"), c1(" | "), c2("99"));
+        assert_eq!(split_message(&text, 100), vec![text]);
+    }
+
+    #[test]
+    fn test_splits_at_separator() {
+        let parts: Vec<String> = (0..5).map(|i| format!("{} {}", l("Skill"), c2(i * 1000))).collect();
+        let text = parts.join(&c1(" | "));
+        let lines = split_message(&text, 60);
+
+        assert!(lines.len() > 1);
+        for line in &lines {
+            assert!(line.len() <= 60, "{:?}", line);
+            assert!(!line.ends_with(" | "));
+        }
+        // Every part survives intact, minus the separators we broke at.
+        assert_eq!(lines.join(&c1(" | ")), text);
+    }
+
+    #[test]
+    fn test_continuation_reemits_color() {
+        let text = c2("one two three four five six");
+        let lines = split_message(&text, 18);
+
+        assert_eq!(lines[0], "\x0304one two three");
+        for line in &lines[1..] {
+            assert!(line.starts_with("\x0304"), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn test_continuation_normalizes_color() {
+        let text = "\x034aaaa 1234";
+        let lines = split_message(text, 7);
+        assert_eq!(lines, vec!["\x034aaaa", "\x03041234"]);
+    }
+
+    #[test]
+    fn test_background_color_carried() {
+        let text = "\x0301,08warn warn";
+        let lines = split_message(text, 10);
+        assert_eq!(lines, vec!["\x0301,08warn", "\x0301,08warn"]);
+    }
+
+    #[test]
+    fn test_reset_clears_color() {
+        let text = "\x0304red\x0F plain text";
+        let lines = split_message(text, 12);
+        assert_eq!(lines[1], "plain text");
+    }
+
+    #[test]
+    fn test_never_splits_color_code() {
+        let text = "aaaaaaa\x0304bbbbbbbb";
+        let lines = split_message(text, 9);
+        assert_eq!(lines[0], "aaaaaaa");
+        assert!(lines[1].starts_with("\x0304b"));
+    }
+
+    #[test]
+    fn test_long_word_multibyte() {
+        let text = "ééééé";
+        let lines = split_message(text, 3);
+        assert_eq!(lines.concat(), text);
+        assert!(lines.iter().all(|l| l.len() <= 3));
+    }
+
+    #[test]
+    fn test_multibyte_any_budget() {
+        let text = format!("a{} {} {}", "é".repeat(20), c2("ééééé 🦀🦀🦀 ñandú"), l("Zoë"));
+        let wanted = |s: &str| s.chars().filter(|c| !c.is_ascii()).count();
+
+        for budget in 1..=text.len() + 1 {
+            let lines = split_message(&text, budget);
+            assert_eq!(lines.iter().map(|l| wanted(l)).sum::<usize>(), wanted(&text), "budget {}", budget);
+            if budget >= 16 {
+                assert!(lines.iter().all(|l| l.len() <= budget), "budget {}: {:?}", budget, lines);
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_privmsg_respects_limit() {
+        let parts: Vec<String> = (0..200).map(l).collect();
+        let text = parts.join(&c1(" | "));
+        let prefix = "Reinze!reinze@bot.example.com";
+        let target = "#runescape";
+
+        for line in split_privmsg(&text, prefix, target) {
+            assert!(line.len() + privmsg_overhead(prefix, target) <= IRC_LINE_LIMIT);
+        }
+    }
+
+    #[test]
+    fn test_continuation_carries_bold() {
+        let text = "\x02\x0304bold text";
+        let lines = split_message(text, 9);
+        assert_eq!(lines, vec!["\x02\x0304bold", "\x0304\x02text"]);
+    }
+
+    #[test]
+    fn test_never_splits_hex_code() {
+        let text = "aaaaaaa\x04FF8800bbbb";
+        let lines = split_message(text, 12);
+        assert_eq!(lines[0], "aaaaaaa");
+        assert!(lines[1].starts_with("\x04FF8800b"));
+    }
+
+    #[test]
+    fn test_empty() {
+        assert!(split_message("", 10).is_empty());
+    }
+}
@@ -0,0 +1,366 @@
+pub mod parse;
+
+pub use parse::{parse, strip_formatting};
+
+use std::fmt;
+
+pub const BOLD: char = '\x02';
+pub const COLOR: char = '\x03';
+pub const HEX_COLOR: char = '\x04';
+pub const RESET: char = '\x0F';
+pub const MONOSPACE: char = '\x11';
+pub const REVERSE: char = '\x16';
+pub const ITALIC: char = '\x1D';
+pub const STRIKETHROUGH: char = '\x1E';
+pub const UNDERLINE: char = '\x1F';
+
+/// A foreground or background color: one of the 99 mIRC palette entries, or
+/// a 24-bit `0xRRGGBB` value sent with `\x04`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Irc(u8),
+    Hex(u32),
+}
+
+impl From<u8> for Color {
+    fn from(n: u8) -> Self {
+        Color::Irc(n)
+    }
+}
+
+/// A set of formatting attributes that can be applied to text.
+///
+/// ```ignore
+/// let warning = Style::new().bold().fg(4).bg(1);
+/// warning.apply("Careful")
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub monospace: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn fg<C>(mut self, color: C) -> Self
+    where
+        C: Into<Color>,
+    {
+        self.fg = Some(color.into());
+        self
+    }
+
+    pub fn bg<C>(mut self, color: C) -> Self
+    where
+        C: Into<Color>,
+    {
+        self.bg = Some(color.into());
+        self
+    }
+
+    pub fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    pub fn italic(mut self) -> Self {
+        self.italic = true;
+        self
+    }
+
+    pub fn underline(mut self) -> Self {
+        self.underline = true;
+        self
+    }
+
+    pub fn strikethrough(mut self) -> Self {
+        self.strikethrough = true;
+        self
+    }
+
+    pub fn monospace(mut self) -> Self {
+        self.monospace = true;
+        self
+    }
+
+    pub fn reverse(mut self) -> Self {
+        self.reverse = true;
+        self
+    }
+
+    pub fn is_plain(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The control codes that switch this style on.
+    pub fn prefix(&self) -> String {
+        let mut out = String::new();
+        let irc = |c: Option<Color>| match c {
+            Some(Color::Irc(n)) => Some(n),
+            _ => None,
+        };
+        let hex = |c: Option<Color>| match c {
+            Some(Color::Hex(n)) => Some(n),
+            _ => None,
+        };
+
+        // A background needs a foreground to attach to; 99 is mIRC's
+        // "default" color.
+        match (irc(self.fg), irc(self.bg)) {
+            (Some(fg), Some(bg)) => out.push_str(&format!("{}{:02},{:02}", COLOR, fg, bg)),
+            (Some(fg), None) => out.push_str(&format!("{}{:02}", COLOR, fg)),
+            (None, Some(bg)) => out.push_str(&format!("{}99,{:02}", COLOR, bg)),
+            (None, None) => {}
+        }
+        match (hex(self.fg), hex(self.bg)) {
+            (Some(fg), Some(bg)) => out.push_str(&format!("{}{:06X},{:06X}", HEX_COLOR, fg, bg)),
+            (Some(fg), None) => out.push_str(&format!("{}{:06X}", HEX_COLOR, fg)),
+            (None, Some(bg)) => out.push_str(&format!("{}FFFFFF,{:06X}", HEX_COLOR, bg)),
+            (None, None) => {}
+        }
+
+        for (on, code) in self.toggles() {
+            if on {
+                out.push(code);
+            }
+        }
+
+        out
+    }
+
+    /// The control codes that switch this style back off. Foreground colors
+    /// are left alone, like `c1`/`c2`, since the next piece of output sets
+    /// its own; backgrounds are cleared so they don't bleed.
+    pub fn suffix(&self) -> String {
+        let mut out: String = self
+            .toggles()
+            .iter()
+            .rev()
+            .filter(|(on, _)| *on)
+            .map(|(_, code)| *code)
+            .collect();
+
+        match self.bg {
+            Some(Color::Irc(_)) => out.push(COLOR),
+            Some(Color::Hex(_)) => out.push(HEX_COLOR),
+            None => {}
+        }
+
+        out
+    }
+
+    pub fn apply<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        format!("{}{}{}", self.prefix(), s.to_string(), self.suffix())
+    }
+
+    /// Whether going from this style to `next` turns anything off, which
+    /// adding codes can't do.
+    fn drops(&self, next: &Style) -> bool {
+        let toggles = self.toggles().iter().zip(next.toggles().iter()).any(|(a, b)| a.0 && !b.0);
+        (self.fg.is_some() && next.fg.is_none()) || (self.bg.is_some() && next.bg.is_none()) || toggles
+    }
+
+    fn toggles(&self) -> [(bool, char); 6] {
+        [
+            (self.bold, BOLD),
+            (self.italic, ITALIC),
+            (self.underline, UNDERLINE),
+            (self.strikethrough, STRIKETHROUGH),
+            (self.monospace, MONOSPACE),
+            (self.reverse, REVERSE),
+        ]
+    }
+}
+
+/// A run of text with a single style.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub style: Style,
+    pub text: String,
+}
+
+impl Span {
+    pub fn new<T>(text: T, style: Style) -> Self
+    where
+        T: ToString,
+    {
+        Self {
+            style,
+            text: text.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.style.is_plain() {
+            write!(f, "{}", self.text)
+        } else {
+            write!(f, "{}", self.style.apply(&self.text))
+        }
+    }
+}
+
+/// Render a sequence of spans back into a single IRC-formatted string.
+/// Whatever a span doesn't have but is still in effect from the one before
+/// it, such as a foreground color, is cleared with a reset first.
+pub fn render(spans: &[Span]) -> String {
+    let mut out = String::new();
+    let mut current = Style::new();
+
+    for span in spans {
+        if current.drops(&span.style) {
+            out.push(RESET);
+            current = Style::new();
+        }
+
+        let piece = span.to_string();
+        for token in parse::tokenize(&piece) {
+            current.update(token);
+        }
+        out.push_str(&piece);
+    }
+
+    out
+}
+
+pub fn bold<T>(s: T) -> String
+where
+    T: ToString,
+{
+    Style::new().bold().apply(s)
+}
+
+pub fn italic<T>(s: T) -> String
+where
+    T: ToString,
+{
+    Style::new().italic().apply(s)
+}
+
+pub fn underline<T>(s: T) -> String
+where
+    T: ToString,
+{
+    Style::new().underline().apply(s)
+}
+
+pub fn strikethrough<T>(s: T) -> String
+where
+    T: ToString,
+{
+    Style::new().strikethrough().apply(s)
+}
+
+pub fn monospace<T>(s: T) -> String
+where
+    T: ToString,
+{
+    Style::new().monospace().apply(s)
+}
+
+pub fn reverse<T>(s: T) -> String
+where
+    T: ToString,
+{
+    Style::new().reverse().apply(s)
+}
+
+/// Color `s` with a foreground and background from the mIRC palette.
+pub fn color<T>(s: T, fg: u8, bg: u8) -> String
+where
+    T: ToString,
+{
+    Style::new().fg(fg).bg(bg).apply(s)
+}
+
+/// Color `s` with a 24-bit `0xRRGGBB` foreground.
+pub fn hex<T>(s: T, rgb: u32) -> String
+where
+    T: ToString,
+{
+    Style::new().fg(Color::Hex(rgb)).apply(s)
+}
+
+/// Clear all formatting.
+pub fn reset() -> String {
+    RESET.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::c2;
+
+    #[test]
+    fn test_toggles() {
+        assert_eq!(bold("x"), "\x02x\x02");
+        assert_eq!(italic("x"), "\x1Dx\x1D");
+        assert_eq!(underline("x"), "\x1Fx\x1F");
+        assert_eq!(strikethrough("x"), "\x1Ex\x1E");
+        assert_eq!(monospace("x"), "\x11x\x11");
+        assert_eq!(reverse("x"), "\x16x\x16");
+        assert_eq!(reset(), "\x0F");
+    }
+
+    #[test]
+    fn test_foreground_matches_c_functions() {
+        assert_eq!(Style::new().fg(4).apply("hello"), c2("hello"));
+    }
+
+    #[test]
+    fn test_background() {
+        assert_eq!(color("warn", 4, 1), "\x0304,01warn\x03");
+        assert_eq!(Style::new().bg(8).apply("x"), "\x0399,08x\x03");
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex("x", 0xFF8800), "\x04FF8800x");
+        assert_eq!(
+            Style::new().fg(Color::Hex(0x000000)).bg(Color::Hex(0xFFFFFF)).apply("x"),
+            "\x04000000,FFFFFFx\x04"
+        );
+    }
+
+    #[test]
+    fn test_composes() {
+        let style = Style::new().bold().underline().fg(3);
+        assert_eq!(style.apply("up"), "\x0303\x02\x1Fup\x1F\x02");
+        assert_eq!(bold(c2("rank 1")), "\x02\x0304rank 1\x02");
+    }
+
+    #[test]
+    fn test_digit_safe() {
+        assert_eq!(Style::new().fg(4).apply(5), "\x03045");
+    }
+
+    #[test]
+    fn test_span_render() {
+        let spans = vec![
+            Span::new("plain ", Style::new()),
+            Span::new("loud", Style::new().bold().fg(4)),
+        ];
+        assert_eq!(render(&spans), "plain \x0304\x02loud\x02");
+    }
+
+    #[test]
+    fn test_render_clears_dropped_color() {
+        assert_eq!(render(&parse("\x0304red\x0Fplain")), "\x0304red\x0Fplain");
+        assert_eq!(render(&parse("\x0304a\x03b")), "\x0304a\x0Fb");
+        // A new color replaces the old one without a reset.
+        assert_eq!(render(&parse("\x0304a\x0303b")), "\x0304a\x0303b");
+    }
+}
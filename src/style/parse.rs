@@ -0,0 +1,293 @@
+use super::{Color, Span, Style, BOLD, COLOR, HEX_COLOR, ITALIC, MONOSPACE, RESET, REVERSE, STRIKETHROUGH, UNDERLINE};
+
+/// One piece of an IRC-formatted string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Token<'a> {
+    Text(&'a str),
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Monospace,
+    Reverse,
+    Reset,
+    /// `\x03` or `\x04` with its arguments. A bare code with no arguments
+    /// clears both colors.
+    Color { fg: Option<Color>, bg: Option<Color> },
+}
+
+/// Split an IRC-formatted string into text and control codes.
+///
+/// Color numbers are read the way clients read them: at most two digits, so
+/// `\x03043.14` is color 04 followed by "3.14", while `\x0343.14` is color 43
+/// followed by ".14".
+pub fn tokenize(s: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let mut text_start = 0;
+
+    while i < s.len() {
+        let len = control_len(&s[i..]);
+        if len == 0 {
+            i += s[i..].chars().next().map_or(1, char::len_utf8);
+            continue;
+        }
+
+        if text_start < i {
+            tokens.push(Token::Text(&s[text_start..i]));
+        }
+        tokens.push(control(&s[i..i + len]));
+        i += len;
+        text_start = i;
+    }
+
+    if text_start < s.len() {
+        tokens.push(Token::Text(&s[text_start..]));
+    }
+
+    tokens
+}
+
+/// Parse an IRC-formatted string into spans of uniformly styled text.
+pub fn parse(s: &str) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut style = Style::new();
+
+    for token in tokenize(s) {
+        match token {
+            Token::Text(text) => match spans.last_mut() {
+                Some(last) if last.style == style => last.text.push_str(text),
+                _ => spans.push(Span::new(text, style)),
+            },
+            token => style.update(token),
+        }
+    }
+
+    spans
+}
+
+/// The formatting in effect at the end of `s`.
+pub fn final_style(s: &str) -> Style {
+    let mut style = Style::new();
+    for token in tokenize(s) {
+        style.update(token);
+    }
+    style
+}
+
+/// Remove every formatting code, leaving only the text a user would read.
+pub fn strip_formatting(s: &str) -> String {
+    tokenize(s)
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Text(text) => Some(text),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Style {
+    /// Apply a control token to this style.
+    pub fn update(&mut self, token: Token) {
+        match token {
+            Token::Text(_) => {}
+            Token::Bold => self.bold = !self.bold,
+            Token::Italic => self.italic = !self.italic,
+            Token::Underline => self.underline = !self.underline,
+            Token::Strikethrough => self.strikethrough = !self.strikethrough,
+            Token::Monospace => self.monospace = !self.monospace,
+            Token::Reverse => self.reverse = !self.reverse,
+            Token::Reset => *self = Style::new(),
+            Token::Color { fg: None, .. } => {
+                self.fg = None;
+                self.bg = None;
+            }
+            Token::Color { fg, bg } => {
+                self.fg = fg;
+                if bg.is_some() {
+                    self.bg = bg;
+                }
+            }
+        }
+    }
+}
+
+/// Length in bytes of the control code at the start of `s`, or 0 if `s`
+/// starts with ordinary text.
+pub(crate) fn control_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    match bytes.first().map(|&b| b as char) {
+        Some(COLOR) => color_len(bytes, |b| b.is_ascii_digit(), 2),
+        Some(HEX_COLOR) => color_len(bytes, |b| b.is_ascii_hexdigit(), 6),
+        Some(BOLD | ITALIC | UNDERLINE | STRIKETHROUGH | MONOSPACE | REVERSE | RESET) => 1,
+        _ => 0,
+    }
+}
+
+/// `\x03` takes up to two digits per color; `\x04` takes exactly six hex
+/// digits. A comma only belongs to the code if a background follows it.
+fn color_len(bytes: &[u8], valid: fn(&u8) -> bool, width: usize) -> usize {
+    let digits = |from: usize| {
+        let n = bytes
+            .get(from..)
+            .unwrap_or_default()
+            .iter()
+            .take(width)
+            .take_while(|b| valid(b))
+            .count();
+        if width == 6 && n != 6 {
+            0
+        } else {
+            n
+        }
+    };
+
+    let fg = digits(1);
+    let mut len = 1 + fg;
+    if fg > 0 && bytes.get(len) == Some(&b',') {
+        let bg = digits(len + 1);
+        if bg > 0 {
+            len += 1 + bg;
+        }
+    }
+    len
+}
+
+fn control(code: &str) -> Token<'_> {
+    let mut chars = code.chars();
+    match chars.next() {
+        Some(BOLD) => Token::Bold,
+        Some(ITALIC) => Token::Italic,
+        Some(UNDERLINE) => Token::Underline,
+        Some(STRIKETHROUGH) => Token::Strikethrough,
+        Some(MONOSPACE) => Token::Monospace,
+        Some(REVERSE) => Token::Reverse,
+        Some(COLOR) => colors(&code[1..], |n| n.parse().ok().map(Color::Irc)),
+        Some(HEX_COLOR) => colors(&code[1..], |n| u32::from_str_radix(n, 16).ok().map(Color::Hex)),
+        _ => Token::Reset,
+    }
+}
+
+fn colors(args: &str, read: fn(&str) -> Option<Color>) -> Token<'static> {
+    if args.is_empty() {
+        return Token::Color { fg: None, bg: None };
+    }
+
+    let (fg, bg) = match args.split_once(',') {
+        Some((fg, bg)) => (read(fg), read(bg)),
+        None => (read(args), None),
+    };
+    Token::Color { fg, bg }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::{bold, color, render};
+    use crate::{c1, c2, l, p};
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn test_digit_after_color() {
+        let spans = parse(&c2(3.14));
+        assert_eq!(spans, vec![Span::new("3.14", Style::new().fg(4))]);
+        assert_eq!(strip_formatting(&c2(3.14)), "3.14");
+    }
+
+    #[test]
+    fn test_one_digit_color_swallows_digit() {
+        // This is what clients do, and why colors should be two digits.
+        let spans = parse("\x0343.14");
+        assert_eq!(spans, vec![Span::new(".14", Style::new().fg(43))]);
+    }
+
+    #[test]
+    fn test_strip_l_and_p() {
+        assert_eq!(strip_formatting(&l("Stats")), "[Stats]");
+        assert_eq!(strip_formatting(&p(42)), "(42)");
+        let line = format!("{}{}{}", l("a"), c1(" | "), p("b"));
+        assert_eq!(strip_formatting(&line), "[a] | (b)");
+    }
+
+    #[test]
+    fn test_parse_l() {
+        assert_eq!(
+            parse(&l("x")),
+            vec![
+                Span::new("[", Style::new().fg(14)),
+                Span::new("x", Style::new().fg(4)),
+                Span::new("]", Style::new().fg(14)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_background_and_comma() {
+        assert_eq!(parse(&color("w", 4, 1)), vec![Span::new("w", Style::new().fg(4).bg(1))]);
+        // A comma not followed by digits is text.
+        assert_eq!(parse("\x0304,x"), vec![Span::new(",x", Style::new().fg(4))]);
+        // A new foreground keeps the background.
+        assert_eq!(parse("\x0304,01a\x0302b")[1], Span::new("b", Style::new().fg(2).bg(1)));
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(parse("\x04FF8800hot"), vec![Span::new("hot", Style::new().fg(Color::Hex(0xFF8800)))]);
+        assert_eq!(
+            parse("\x04000000,FFFFFFx"),
+            vec![Span::new("x", Style::new().fg(Color::Hex(0)).bg(Color::Hex(0xFFFFFF)))]
+        );
+        // Incomplete hex codes are a bare \x04 followed by text.
+        assert_eq!(strip_formatting("\x04FF8x"), "FF8x");
+    }
+
+    #[test]
+    fn test_toggles_and_reset() {
+        let spans = parse("a\x02b\x1Dc\x02d\x0Fe");
+        assert_eq!(
+            spans,
+            vec![
+                Span::new("a", Style::new()),
+                Span::new("b", Style::new().bold()),
+                Span::new("c", Style::new().bold().italic()),
+                Span::new("d", Style::new().italic()),
+                Span::new("e", Style::new()),
+            ]
+        );
+        assert_eq!(strip_formatting("\x1Fu\x1E\x11\x16\x0F"), "u");
+    }
+
+    #[test]
+    fn test_bare_color_clears() {
+        assert_eq!(parse("\x0304,01a\x03b")[1], Span::new("b", Style::new()));
+    }
+
+    #[test]
+    fn test_merges_redundant_codes() {
+        assert_eq!(parse("\x0304a\x0304b"), vec![Span::new("ab", Style::new().fg(4))]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for original in [
+            format!("{} {}", bold(c2("x")), color("y", 3, 1)),
+            "\x0304red\x0Fplain".to_string(),
+            "\x0304a\x03b".to_string(),
+            "\x02\x0304,01loud\x0F 42".to_string(),
+        ] {
+            assert_eq!(strip_formatting(&render(&parse(&original))), strip_formatting(&original));
+            assert_eq!(parse(&render(&parse(&original))), parse(&original), "{:?}", original);
+        }
+    }
+
+    #[test]
+    fn test_final_style() {
+        assert_eq!(final_style("\x0304,01a\x02"), Style::new().fg(4).bg(1).bold());
+        assert_eq!(final_style("plain"), Style::new());
+    }
+
+    #[test]
+    fn test_unicode_text() {
+        assert_eq!(strip_formatting("\x0304über\x02ß"), "überß");
+    }
+}
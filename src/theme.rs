@@ -0,0 +1,585 @@
+use crate::{commas_int, database, Colors, IrcColor};
+use anyhow::{bail, Context, Result};
+use arc_swap::ArcSwap;
+use mysql::params;
+use mysql::prelude::Queryable;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::c_char;
+use std::sync::{Arc, LazyLock};
+
+/// Colors for each semantic role in plugin output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Theme {
+    pub label: IrcColor,
+    pub value: IrcColor,
+    pub separator: IrcColor,
+    pub positive: IrcColor,
+    pub negative: IrcColor,
+    pub neutral: IrcColor,
+    pub highlight: IrcColor,
+    pub muted: IrcColor,
+    /// Emit text without any control codes; see `Colors::plain`.
+    pub plain: bool,
+}
+
+impl Default for Theme {
+    // Matches the historical c1..c5 colors.
+    fn default() -> Self {
+        let color = |n| IrcColor::new(n).unwrap();
+
+        Self {
+            label: Colors::color1(),
+            value: Colors::color2(),
+            separator: Colors::color1(),
+            positive: color(3),
+            negative: color(5),
+            neutral: color(7),
+            highlight: color(7),
+            muted: color(15),
+            plain: false,
+        }
+    }
+}
+
+impl Theme {
+    pub fn label<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        self.wrap(self.label, s)
+    }
+
+    pub fn value<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        self.wrap(self.value, s)
+    }
+
+    pub fn positive<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        self.wrap(self.positive, s)
+    }
+
+    pub fn negative<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        self.wrap(self.negative, s)
+    }
+
+    pub fn neutral<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        self.wrap(self.neutral, s)
+    }
+
+    pub fn highlight<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        self.wrap(self.highlight, s)
+    }
+
+    pub fn muted<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        self.wrap(self.muted, s)
+    }
+
+    /// A signed change with thousands separators, colored by direction:
+    /// `+1,234` positive, `-567` negative, `0` neutral.
+    pub fn delta(&self, n: i64) -> String {
+        let formatted = commas_int(n.unsigned_abs());
+
+        match n {
+            n if n > 0 => self.positive(format!("+{}", formatted)),
+            n if n < 0 => self.negative(format!("-{}", formatted)),
+            _ => self.neutral(formatted),
+        }
+    }
+
+    /// The ` | ` separator between output fields.
+    pub fn separator(&self) -> String {
+        self.wrap(self.separator, " | ")
+    }
+
+    /// Join fields with the separator, like `unranked`/`not_found` do.
+    pub fn join(&self, v: &[String]) -> String {
+        v.join(&self.separator())
+    }
+
+    pub fn l<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        format!("{}{}{}", self.label("["), self.value(s), self.label("]"))
+    }
+
+    pub fn p<T>(&self, s: T) -> String
+    where
+        T: ToString,
+    {
+        format!("{}{}{}", self.label("("), self.value(s), self.label(")"))
+    }
+
+    fn wrap<T>(&self, color: IrcColor, s: T) -> String
+    where
+        T: ToString,
+    {
+        if self.plain {
+            s.to_string()
+        } else {
+            format!("\x03{}{}", color, s.to_string())
+        }
+    }
+
+    /// Every role of this theme as overrides, which is how the host hands a
+    /// resolved theme to plugins. `plain` travels separately.
+    pub fn to_overrides(&self) -> ThemeOverrides {
+        Role::ALL
+            .iter()
+            .fold(ThemeOverrides::new(), |overrides, &role| overrides.set(role, self.role(role)))
+    }
+
+    /// Apply overrides on top of this theme.
+    pub fn merge(mut self, overrides: &ThemeOverrides) -> Self {
+        for role in Role::ALL {
+            if let Some(color) = overrides.get(role) {
+                *self.role_mut(role) = color;
+            }
+        }
+        self
+    }
+
+    fn role(mut self, role: Role) -> IrcColor {
+        *self.role_mut(role)
+    }
+
+    fn role_mut(&mut self, role: Role) -> &mut IrcColor {
+        match role {
+            Role::Label => &mut self.label,
+            Role::Value => &mut self.value,
+            Role::Separator => &mut self.separator,
+            Role::Positive => &mut self.positive,
+            Role::Negative => &mut self.negative,
+            Role::Neutral => &mut self.neutral,
+            Role::Highlight => &mut self.highlight,
+            Role::Muted => &mut self.muted,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Role {
+    Label,
+    Value,
+    Separator,
+    Positive,
+    Negative,
+    Neutral,
+    Highlight,
+    Muted,
+}
+
+impl Role {
+    pub const ALL: [Role; 8] = [
+        Role::Label,
+        Role::Value,
+        Role::Separator,
+        Role::Positive,
+        Role::Negative,
+        Role::Neutral,
+        Role::Highlight,
+        Role::Muted,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Label => "label",
+            Role::Value => "value",
+            Role::Separator => "separator",
+            Role::Positive => "positive",
+            Role::Negative => "negative",
+            Role::Neutral => "neutral",
+            Role::Highlight => "highlight",
+            Role::Muted => "muted",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Role::ALL
+            .iter()
+            .copied()
+            .find(|role| role.name().eq_ignore_ascii_case(s.trim()))
+            .with_context(|| format!("unknown theme role {:?}", s))
+    }
+}
+
+/// The roles a scope sets; anything unset falls through to the next scope.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ThemeOverrides {
+    colors: HashMap<Role, IrcColor>,
+}
+
+impl ThemeOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(mut self, role: Role, color: IrcColor) -> Self {
+        self.colors.insert(role, color);
+        self
+    }
+
+    pub fn get(&self, role: Role) -> Option<IrcColor> {
+        self.colors.get(&role).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// A user's personal `Colors` expressed as overrides: `c1` for labels and
+    /// separators, `c2` for values.
+    pub fn from_colors(colors: &Colors) -> Self {
+        Self::new()
+            .set(Role::Label, colors.c1)
+            .set(Role::Separator, colors.c1)
+            .set(Role::Value, colors.c2)
+    }
+}
+
+/// Parses the stored form, e.g. `label=14,value=04,positive=09`.
+impl std::str::FromStr for ThemeOverrides {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut overrides = Self::new();
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let Some((role, color)) = pair.split_once('=') else {
+                bail!("invalid theme entry {:?}, expected role=color", pair);
+            };
+            overrides = overrides.set(role.parse()?, color.parse()?);
+        }
+        Ok(overrides)
+    }
+}
+
+impl fmt::Display for ThemeOverrides {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pairs: Vec<String> = Role::ALL
+            .iter()
+            .copied()
+            .filter_map(|role| self.get(role).map(|color| format!("{}={}", role.name(), color)))
+            .collect();
+        write!(f, "{}", pairs.join(","))
+    }
+}
+
+/// Where a set of overrides applies. More specific scopes win.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Scope {
+    Global,
+    Network(String),
+    Channel { network: String, channel: String },
+    Host(String),
+}
+
+impl Scope {
+    /// The key the scope is stored under. Networks and channels are
+    /// case-insensitive on IRC, so they're lowercased.
+    pub fn key(&self) -> String {
+        match self {
+            Scope::Global => "global".to_string(),
+            Scope::Network(network) => format!("network:{}", network.to_lowercase()),
+            Scope::Channel { network, channel } => {
+                format!("channel:{}/{}", network.to_lowercase(), channel.to_lowercase())
+            }
+            Scope::Host(host) => format!("host:{}", host),
+        }
+    }
+}
+
+/// Parses a `Scope::key()` back into its scope.
+impl std::str::FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "global" {
+            return Ok(Scope::Global);
+        }
+
+        let scope = match s.split_once(':') {
+            Some(("network", network)) if !network.is_empty() => Scope::Network(network.to_string()),
+            Some(("channel", rest)) => match rest.split_once('/') {
+                Some((network, channel)) if !network.is_empty() && !channel.is_empty() => Scope::Channel {
+                    network: network.to_string(),
+                    channel: channel.to_string(),
+                },
+                _ => bail!("invalid channel scope {:?}, expected channel:<network>/<channel>", s),
+            },
+            Some(("host", host)) if !host.is_empty() => Scope::Host(host.to_string()),
+            _ => bail!("invalid scope {:?}", s),
+        };
+        Ok(scope)
+    }
+}
+
+type ThemeMap = HashMap<String, ThemeOverrides>;
+
+static THEMES: LazyLock<ArcSwap<ThemeMap>> = LazyLock::new(|| ArcSwap::from_pointee(HashMap::new()));
+
+/// Resolve the theme for a user, falling back from their host to the
+/// channel, then the network, then the global default.
+///
+/// The user's personal `Colors` apply at the host level, but only when they
+/// differ from the defaults, so a channel theme isn't masked by a user who
+/// never picked colors.
+pub fn resolve(host: &str, network: &str, channel: &str, colors: Option<&Colors>) -> Theme {
+    let themes = THEMES.load();
+    let lookup = |scope: Scope| themes.get(&scope.key()).cloned().unwrap_or_default();
+
+    let mut theme = Theme::default().merge(&lookup(Scope::Global));
+    if !network.is_empty() {
+        theme = theme.merge(&lookup(Scope::Network(network.to_string())));
+        if !channel.is_empty() {
+            theme = theme.merge(&lookup(Scope::Channel {
+                network: network.to_string(),
+                channel: channel.to_string(),
+            }));
+        }
+    }
+    if let Some(colors) = colors {
+        theme.plain = colors.plain;
+        if *colors != Colors::default().with_plain(colors.plain) {
+            theme = theme.merge(&ThemeOverrides::from_colors(colors));
+        }
+    }
+
+    theme.merge(&lookup(Scope::Host(host.to_string())))
+}
+
+/// The overrides stored for a scope, from the in-memory cache.
+pub fn get(scope: &Scope) -> ThemeOverrides {
+    THEMES.load().get(&scope.key()).cloned().unwrap_or_default()
+}
+
+/// Set the overrides for a scope in memory only.
+pub fn upsert(scope: &Scope, overrides: ThemeOverrides) {
+    let key = scope.key();
+
+    THEMES.rcu(|current| {
+        let mut new_map = (**current).clone();
+        if overrides.is_empty() {
+            new_map.remove(&key);
+        } else {
+            new_map.insert(key.clone(), overrides.clone());
+        }
+        Arc::new(new_map)
+    });
+}
+
+/// Set the overrides for a scope and persist them. The in-memory part only
+/// reaches the calling library, so plugins use `Source::set_theme`, which
+/// goes through the host.
+pub fn set(scope: &Scope, overrides: ThemeOverrides) -> Result<()> {
+    upsert(scope, overrides.clone());
+
+    let mut conn = database::connect()
+        .map_err(|e| anyhow::anyhow!("database connection failed: {}", e))?;
+
+    conn.exec_drop(
+        "INSERT INTO themes (scope, theme) VALUES (:scope, :theme) ON DUPLICATE KEY UPDATE theme = :theme",
+        params! { "scope" => scope.key(), "theme" => overrides.to_string() },
+    )
+    .context("failed to save theme")?;
+
+    Ok(())
+}
+
+/// The `set_theme` callback handed to plugins: stores `theme` (in the
+/// stored form, empty to clear) for the scope whose key is `scope`.
+///
+/// # Safety
+/// `scope` and `theme` must point to valid nul-terminated strings.
+pub unsafe extern "C" fn theme_ffi(scope: *const c_char, theme: *const c_char) -> bool {
+    let scope = CStr::from_ptr(scope).to_str().unwrap_or_default();
+    let theme = CStr::from_ptr(theme).to_str().unwrap_or_default();
+
+    let result = scope
+        .parse::<Scope>()
+        .and_then(|scope| Ok((scope, theme.parse::<ThemeOverrides>()?)))
+        .and_then(|(scope, overrides)| set(&scope, overrides));
+
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("theme_ffi: {}", e);
+            false
+        }
+    }
+}
+
+/// Load every stored theme into memory. `Colors::init` calls this at
+/// startup; entries that no longer parse are logged and skipped.
+pub fn load() -> Result<()> {
+    let mut conn = database::connect()
+        .map_err(|e| anyhow::anyhow!("database connection failed: {}", e))?;
+
+    let rows: Vec<(String, String)> = conn
+        .query("SELECT scope, theme FROM themes")
+        .context("failed to query themes")?;
+
+    let mut map = HashMap::new();
+    for (scope, theme) in rows {
+        match theme.parse::<ThemeOverrides>() {
+            Ok(overrides) => {
+                map.insert(scope, overrides);
+            }
+            Err(e) => log::error!("Invalid theme for {}: {}", scope, e),
+        }
+    }
+
+    THEMES.store(Arc::new(map));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{c1, c2, c3, c4, c5, l, p};
+
+    fn color(n: u8) -> IrcColor {
+        IrcColor::new(n).unwrap()
+    }
+
+    #[test]
+    fn test_default_matches_free_functions() {
+        let theme = Theme::default();
+        assert_eq!(theme.label("x"), c1("x"));
+        assert_eq!(theme.value("x"), c2("x"));
+        assert_eq!(theme.negative("x"), c3("x"));
+        assert_eq!(theme.positive("x"), c4("x"));
+        assert_eq!(theme.neutral("x"), c5("x"));
+        assert_eq!(theme.highlight("x"), c5("x"));
+        assert_eq!(theme.l("x"), l("x"));
+        assert_eq!(theme.p("x"), p("x"));
+        assert_eq!(theme.separator(), c1(" | "));
+    }
+
+    #[test]
+    fn test_plain() {
+        let theme = resolve("plain@host", "", "", Some(&Colors::default().with_plain(true)));
+        assert_eq!(theme.positive("+5"), "+5");
+        assert_eq!(theme.l("x"), "[x]");
+        assert_eq!(theme.separator(), " | ");
+    }
+
+    #[test]
+    fn test_overrides_parse_and_display() {
+        let overrides: ThemeOverrides = "label=2, Positive=9".parse().unwrap();
+        assert_eq!(overrides.get(Role::Label), Some(color(2)));
+        assert_eq!(overrides.get(Role::Positive), Some(color(9)));
+        assert_eq!(overrides.get(Role::Value), None);
+        assert_eq!(overrides.to_string(), "label=02,positive=09");
+
+        assert!("label".parse::<ThemeOverrides>().is_err());
+        assert!("colour=04".parse::<ThemeOverrides>().is_err());
+        assert!("label=red".parse::<ThemeOverrides>().is_err());
+        assert!("".parse::<ThemeOverrides>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scope_keys() {
+        assert_eq!(Scope::Global.key(), "global");
+        assert_eq!(Scope::Network("Rizon".into()).key(), "network:rizon");
+        assert_eq!(
+            Scope::Channel {
+                network: "Rizon".into(),
+                channel: "#RS".into()
+            }
+            .key(),
+            "channel:rizon/#rs"
+        );
+        assert_eq!(Scope::Host("i@h".into()).key(), "host:i@h");
+    }
+
+    #[test]
+    fn test_scope_parse() {
+        let scopes = [
+            Scope::Global,
+            Scope::Network("rizon".into()),
+            Scope::Channel {
+                network: "rizon".into(),
+                channel: "#rs".into(),
+            },
+            Scope::Host("i@2001:db8::1".into()),
+        ];
+        for scope in scopes {
+            assert_eq!(scope.key().parse::<Scope>().unwrap(), scope);
+        }
+        assert!("channel:rizon".parse::<Scope>().is_err());
+        assert!("host:".parse::<Scope>().is_err());
+        assert!("planet:earth".parse::<Scope>().is_err());
+    }
+
+    #[test]
+    fn test_to_overrides() {
+        let theme = Theme::default().merge(&"positive=09".parse().unwrap());
+        let overrides = theme.to_overrides();
+        assert_eq!(overrides.get(Role::Positive), Some(color(9)));
+        assert_eq!(Theme::default().merge(&overrides.to_string().parse().unwrap()), theme);
+    }
+
+    #[test]
+    fn test_resolve_hierarchy() {
+        let network = "themetest-net";
+        upsert(&Scope::Network(network.into()), "positive=09,label=02".parse().unwrap());
+        upsert(
+            &Scope::Channel {
+                network: network.into(),
+                channel: "#themed".into(),
+            },
+            "positive=10".parse().unwrap(),
+        );
+        upsert(&Scope::Host("themed@host".into()), "negative=04".parse().unwrap());
+
+        let theme = resolve("other@host", network, "#plain", None);
+        assert_eq!(theme.positive, color(9));
+        assert_eq!(theme.label, color(2));
+        assert_eq!(theme.negative, Theme::default().negative);
+
+        let theme = resolve("other@host", network, "#THEMED", None);
+        assert_eq!(theme.positive, color(10));
+        assert_eq!(theme.label, color(2));
+
+        let theme = resolve("themed@host", network, "#themed", None);
+        assert_eq!(theme.positive, color(10));
+        assert_eq!(theme.negative, color(4));
+
+        // Default personal colors don't mask the network label...
+        let theme = resolve("other@host", network, "", Some(&Colors::default()));
+        assert_eq!(theme.label, color(2));
+
+        // ...but chosen ones do.
+        let colors = Colors::from_codes("06", "07").unwrap();
+        let theme = resolve("other@host", network, "", Some(&colors));
+        assert_eq!(theme.label, color(6));
+        assert_eq!(theme.value, color(7));
+        assert_eq!(theme.positive, color(9));
+
+        upsert(&Scope::Network(network.into()), ThemeOverrides::new());
+        assert!(get(&Scope::Network(network.into())).is_empty());
+    }
+}
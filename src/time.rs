@@ -0,0 +1,296 @@
+use anyhow::{bail, Result};
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+pub const MINUTE: u64 = 60;
+pub const HOUR: u64 = 60 * MINUTE;
+pub const DAY: u64 = 24 * HOUR;
+pub const WEEK: u64 = 7 * DAY;
+/// Calendar-free month and year, for durations like `2mo` or `1y`.
+pub const MONTH: u64 = 30 * DAY;
+pub const YEAR: u64 = 365 * DAY;
+
+// (seconds, compact suffix, long name)
+const UNITS: [(u64, &str, &str); 5] = [
+    (WEEK, "w", "week"),
+    (DAY, "d", "day"),
+    (HOUR, "h", "hour"),
+    (MINUTE, "m", "minute"),
+    (1, "s", "second"),
+];
+
+/// `Compact` is "2w 3d", the same units `snapshot::parse_duration` reads;
+/// `Long` is "2 weeks, 3 days".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    Compact,
+    Long,
+}
+
+/// Format `d` in compact form down to the second: `2w 3d`, `1h 30m`.
+pub fn format_duration(d: Duration) -> String {
+    format_duration_with(d, Style::Compact, UNITS.len())
+}
+
+/// Format `d` in long form: `2 weeks, 3 days`.
+pub fn format_duration_long(d: Duration) -> String {
+    format_duration_with(d, Style::Long, UNITS.len())
+}
+
+/// Format a number of hours.
+pub fn format_hours(hours: u64) -> String {
+    format_duration(Duration::from_secs(hours.saturating_mul(HOUR)))
+}
+
+/// Format `d` using at most `max_units` of its largest units, dropping
+/// (not rounding) the rest: with 2 units, 1w 2d 5h is `1w 2d`.
+pub fn format_duration_with(d: Duration, style: Style, max_units: usize) -> String {
+    let mut rest = d.as_secs();
+    let mut parts = Vec::new();
+
+    for (size, suffix, name) in UNITS.iter().copied() {
+        let n = rest / size;
+        rest %= size;
+        if n == 0 || parts.len() >= max_units.max(1) {
+            continue;
+        }
+        parts.push(match style {
+            Style::Compact => format!("{}{}", n, suffix),
+            Style::Long => plural(n, name),
+        });
+    }
+
+    match (parts.is_empty(), style) {
+        (true, Style::Compact) => "0s".to_string(),
+        (true, Style::Long) => plural(0, "second"),
+        (false, Style::Compact) => parts.join(" "),
+        (false, Style::Long) => parts.join(", "),
+    }
+}
+
+/// How long ago `then` was, in its largest whole unit: `3 days ago`,
+/// `1 month ago`. Times in the future read `in 3 days`.
+pub fn time_ago(then: SystemTime) -> String {
+    time_ago_at(then, SystemTime::now())
+}
+
+/// `time_ago` as seen from `now`.
+pub fn time_ago_at(then: SystemTime, now: SystemTime) -> String {
+    let (elapsed, future) = match now.duration_since(then) {
+        Ok(elapsed) => (elapsed, false),
+        Err(e) => (e.duration(), true),
+    };
+
+    let secs = elapsed.as_secs();
+    if secs == 0 {
+        return "just now".to_string();
+    }
+
+    // Days rather than weeks, so two and a half weeks is "17 days ago".
+    let (size, name) = [
+        (YEAR, "year"),
+        (MONTH, "month"),
+        (DAY, "day"),
+        (HOUR, "hour"),
+        (MINUTE, "minute"),
+    ]
+    .iter()
+    .copied()
+    .find(|&(size, _)| secs >= size)
+    .unwrap_or((1, "second"));
+
+    let amount = plural(secs / size, name);
+    if future {
+        format!("in {}", amount)
+    } else {
+        format!("{} ago", amount)
+    }
+}
+
+/// A calendar date, read as midnight UTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    pub fn new(year: i32, month: u32, day: u32) -> Result<Self> {
+        if !(1..=12).contains(&month) {
+            bail!("invalid month {} in {:04}-{:02}-{:02}", month, year, month, day);
+        }
+        if day == 0 || day > days_in_month(year, month) {
+            bail!("invalid day {} in {:04}-{:02}-{:02}", day, year, month, day);
+        }
+        Ok(Self { year, month, day })
+    }
+
+    /// Days since 1970-01-01, negative before it.
+    pub fn days_since_epoch(&self) -> i64 {
+        // Howard Hinnant's days_from_civil, with years starting in March.
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = if year >= 0 { year } else { year - 399 } / 400;
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * i64::from((self.month + 9) % 12) + 2) / 5 + i64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    pub fn to_system_time(&self) -> SystemTime {
+        let days = self.days_since_epoch();
+        let secs = Duration::from_secs(days.unsigned_abs() * DAY);
+        if days >= 0 {
+            SystemTime::UNIX_EPOCH + secs
+        } else {
+            SystemTime::UNIX_EPOCH - secs
+        }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A point to look back to: a span of time before now, or a fixed date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Since {
+    Ago(Duration),
+    Date(Date),
+}
+
+impl Since {
+    /// The point in time this refers to, as seen from `now`.
+    pub fn at(&self, now: SystemTime) -> SystemTime {
+        match self {
+            Since::Ago(d) => now.checked_sub(*d).unwrap_or(SystemTime::UNIX_EPOCH),
+            Since::Date(date) => date.to_system_time(),
+        }
+    }
+
+    /// How far back from `now` this reaches; zero for dates in the future.
+    pub fn duration_at(&self, now: SystemTime) -> Duration {
+        now.duration_since(self.at(now)).unwrap_or_default()
+    }
+}
+
+/// `2w 3d` for a span, `2026-09-01` for a date.
+impl fmt::Display for Since {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Since::Ago(d) => write!(f, "{}", format_duration(*d)),
+            Since::Date(date) => write!(f, "{}", date),
+        }
+    }
+}
+
+fn plural(n: u64, name: &str) -> String {
+    if n == 1 {
+        format!("{} {}", n, name)
+    } else {
+        format!("{} {}s", n, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::parse_duration;
+    use anyhow::Result;
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(secs(2 * WEEK + 3 * DAY)), "2w 3d");
+        assert_eq!(format_duration(secs(HOUR + 30 * MINUTE)), "1h 30m");
+        assert_eq!(format_duration(secs(45)), "45s");
+        assert_eq!(format_duration(secs(WEEK + 1)), "1w 1s");
+        assert_eq!(format_duration(Duration::from_millis(999)), "0s");
+    }
+
+    #[test]
+    fn test_format_duration_long() {
+        assert_eq!(format_duration_long(secs(2 * WEEK + 3 * DAY)), "2 weeks, 3 days");
+        assert_eq!(format_duration_long(secs(DAY + HOUR)), "1 day, 1 hour");
+        assert_eq!(format_duration_long(secs(0)), "0 seconds");
+    }
+
+    #[test]
+    fn test_max_units() {
+        let d = secs(WEEK + 2 * DAY + 5 * HOUR + 10);
+        assert_eq!(format_duration_with(d, Style::Compact, 2), "1w 2d");
+        assert_eq!(format_duration_with(d, Style::Long, 1), "1 week");
+        assert_eq!(format_duration_with(d, Style::Compact, 0), "1w");
+    }
+
+    #[test]
+    fn test_format_inverts_parse_duration() -> Result<()> {
+        for s in ["2w 3d", "1w 1d 1h", "12h", "3d", "1h 30m"] {
+            assert_eq!(parse_duration(s)?.to_string(), s);
+        }
+        assert_eq!(format_hours(408), "2w 3d");
+        assert!(!format_hours(u64::MAX).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_date() -> Result<()> {
+        assert_eq!(Date::new(1970, 1, 1)?.days_since_epoch(), 0);
+        assert_eq!(Date::new(2000, 3, 1)?.days_since_epoch(), 11_017);
+        assert_eq!(Date::new(1969, 12, 31)?.days_since_epoch(), -1);
+        assert_eq!(Date::new(2026, 9, 1)?.to_string(), "2026-09-01");
+        assert!(Date::new(2024, 2, 29).is_ok());
+        assert!(Date::new(2026, 2, 29).is_err());
+        assert!(Date::new(1900, 2, 29).is_err());
+        assert!(Date::new(2026, 13, 1).is_err());
+        assert!(Date::new(2026, 4, 31).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_since_at() -> Result<()> {
+        let date = Date::new(2026, 9, 1)?;
+        let now = date.to_system_time() + secs(3 * DAY + HOUR);
+
+        assert_eq!(Since::Date(date).duration_at(now), secs(3 * DAY + HOUR));
+        assert_eq!(Since::Ago(secs(DAY)).at(now), now - secs(DAY));
+        assert_eq!(Since::Date(Date::new(2030, 1, 1)?).duration_at(now), Duration::ZERO);
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_ago() {
+        let now = SystemTime::UNIX_EPOCH + secs(100 * YEAR);
+        let ago = |n: u64| time_ago_at(now - secs(n), now);
+
+        assert_eq!(ago(0), "just now");
+        assert_eq!(ago(1), "1 second ago");
+        assert_eq!(ago(59), "59 seconds ago");
+        assert_eq!(ago(90), "1 minute ago");
+        assert_eq!(ago(3 * HOUR), "3 hours ago");
+        assert_eq!(ago(3 * DAY), "3 days ago");
+        assert_eq!(ago(17 * DAY), "17 days ago");
+        assert_eq!(ago(45 * DAY), "1 month ago");
+        assert_eq!(ago(2 * YEAR + DAY), "2 years ago");
+    }
+
+    #[test]
+    fn test_time_ago_future() {
+        let now = SystemTime::UNIX_EPOCH + secs(YEAR);
+        assert_eq!(time_ago_at(now + secs(3 * DAY), now), "in 3 days");
+    }
+}
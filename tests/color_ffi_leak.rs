@@ -0,0 +1,51 @@
+//! Lives in its own test binary so the counting allocator only sees this
+//! test, and frees through the exported `reinze_free_color_result` the way a
+//! host does.
+
+use reinze_lib_common::author::cache::{color_ffi, init, upsert_color};
+use reinze_lib_common::ffi::{reinze_free_color_result, ColorResultGuard};
+use reinze_lib_common::Colors;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::ffi::CString;
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+struct CountingAlloc;
+
+static LIVE: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(1, Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+#[test]
+fn test_color_ffi_does_not_leak() {
+    init();
+    upsert_color("leak@check.com".to_string(), Colors::from_codes("03", "07").unwrap());
+
+    let host = CString::new("leak@check.com").unwrap();
+    let empty = CString::default();
+    let fetch = || {
+        let guard = unsafe { ColorResultGuard::new(color_ffi(host.as_ptr(), empty.as_ptr()), reinze_free_color_result) };
+        (guard.c1(), guard.c2())
+    };
+
+    // Warm up any lazily allocated state first.
+    assert_eq!(fetch(), ("03".to_string(), "07".to_string()));
+
+    let before = LIVE.load(Ordering::SeqCst);
+    for _ in 0..1000 {
+        drop(fetch());
+    }
+    assert_eq!(LIVE.load(Ordering::SeqCst), before);
+}